@@ -0,0 +1,83 @@
+// Headless benchmark of World::update, for sizing a server.
+// Builds a world of the given size (the classic world is 1000, a 10000 world holds about
+// 100k spheres), adds real players and runs it at 60 updates per second as fast as it can.
+//
+//   cargo run --release --example benchmark -- [size] [players] [seconds] [flags]
+//
+// flags: sleep (freeze sectors without players).
+// build with `--features parallel` to use every core.
+
+use std::time::Instant;
+
+use ubur::{config::WorldConfig, world::World};
+
+fn main() {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let number = |index: usize, default: f64| -> f64 {
+        match args.get(index).and_then(|arg| arg.parse().ok()) {
+            Some(value) => value,
+            None => default,
+        }
+    };
+
+    let size = number(0, 10000.0);
+    let players = number(1, 8.0) as usize;
+    let seconds = number(2, 10.0);
+
+    let mut config = WorldConfig::new();
+    config.sleep = args.iter().any(|arg| arg == "sleep");
+
+    let mut world = World::with_config(config);
+    world.set_size(size);
+
+    let start = Instant::now();
+    world.init();
+
+    for i in 0..players {
+        let _ = world.spawn_player(format!("player {}", i));
+    }
+
+    println!(
+        "size {} with {} sectors, {} spheres after init in {:.0} ms, {} threads",
+        size,
+        world.sectors.len(),
+        world.spheres.len(),
+        start.elapsed().as_secs_f64() * 1000.0,
+        world.threads,
+    );
+
+    let dt = 1.0 / 60.0;
+    let ticks = usize::max((seconds / dt) as usize, 1);
+    let mut total = 0.0;
+    let mut max: f64 = 0.0;
+    let mut awake = 0;
+
+    for tick in 0..ticks {
+        let start = Instant::now();
+        world.update(dt);
+        let elapsed = start.elapsed().as_secs_f64() * 1000.0;
+
+        total += elapsed;
+        max = f64::max(max, elapsed);
+        awake += world.awake_ids.len();
+
+        if (tick + 1) % 600 == 0 {
+            println!(
+                "{:>6} ticks: {} spheres, {} awake in {} sectors, {} slots",
+                tick + 1,
+                world.spheres.len(),
+                world.awake_ids.len(),
+                world.sectors.get_awake_count(),
+                world.spheres.capacity(),
+            );
+        }
+    }
+
+    println!(
+        "{} ticks, {:.3} ms per tick on average, {:.3} ms at most, {} awake spheres on average",
+        ticks,
+        total / ticks as f64,
+        max,
+        awake / ticks,
+    );
+}
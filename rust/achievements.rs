@@ -0,0 +1,532 @@
+// Achievements of the local player, driven by the world events.
+// Every achievement is a definition (what stat to watch and the goal to reach) plus the
+// progress toward it. Definitions are plain data, so new ones can be added from JS
+// without touching the engine. The progress serializes to a text blob like the high scores.
+
+use std::collections::HashMap;
+
+use wasm_bindgen::prelude::*;
+
+use crate::{
+    error::{check_positive, ApiError},
+    events::WorldEvent,
+};
+
+static BLOB_HEADER: &str = "ubur-achievements 1";
+
+// SCORE and TIMEALIVE are measured every update, the others are counters
+// that go up by one with every matching event
+#[wasm_bindgen]
+#[derive(PartialEq, Clone, Copy)]
+pub enum AchievementStat {
+    // score of the current life
+    SCORE,
+    // seconds alive in the current life
+    TIMEALIVE,
+    // player spheres eaten
+    EATS,
+    // player spheres that died shortly after being hit by a bullet
+    BULLETKILLS,
+    // anti matter touched and survived
+    AMESCAPES,
+}
+
+#[wasm_bindgen]
+#[derive(Clone)]
+pub struct AchievementDef {
+    // stable key, used to store the progress
+    #[wasm_bindgen(getter_with_clone)]
+    pub key: String,
+    #[wasm_bindgen(getter_with_clone)]
+    pub name: String,
+    #[wasm_bindgen(getter_with_clone)]
+    pub description: String,
+    pub stat: AchievementStat,
+    pub goal: f64,
+    // counters only count within one life, and reset when the player dies
+    pub per_life: bool,
+    // events only count while the player radius is at least this
+    pub min_r: f64,
+}
+
+#[wasm_bindgen]
+impl AchievementDef {
+    pub fn new(
+        key: String,
+        name: String,
+        description: String,
+        stat: AchievementStat,
+        goal: f64,
+        per_life: bool,
+        min_r: f64,
+    ) -> Result<AchievementDef, ApiError> {
+        check_positive(&[goal, min_r])?;
+
+        return Ok(AchievementDef {
+            key,
+            name,
+            description,
+            stat,
+            goal,
+            per_life,
+            min_r,
+        });
+    }
+}
+
+pub fn default_definitions() -> Vec<AchievementDef> {
+    // (key, name, description, stat, goal, per_life, min_r)
+    let defs = [
+        (
+            "score-10000",
+            "Heavyweight",
+            "Reach a score of 10 000",
+            AchievementStat::SCORE,
+            10000.0,
+            true,
+            0.0,
+        ),
+        (
+            "eat-5-players",
+            "Glutton",
+            "Eat 5 players in one life",
+            AchievementStat::EATS,
+            5.0,
+            true,
+            0.0,
+        ),
+        (
+            "survive-10-minutes",
+            "Survivor",
+            "Stay alive for 10 minutes",
+            AchievementStat::TIMEALIVE,
+            600.0,
+            true,
+            0.0,
+        ),
+        (
+            "bullet-kill",
+            "Sharpshooter",
+            "Kill a player with a bullet",
+            AchievementStat::BULLETKILLS,
+            1.0,
+            false,
+            0.0,
+        ),
+        (
+            "escape-am-large",
+            "Close Call",
+            "Escape anti matter while large",
+            AchievementStat::AMESCAPES,
+            1.0,
+            false,
+            50.0,
+        ),
+    ];
+
+    defs.iter()
+        .map(
+            |(key, name, description, stat, goal, per_life, min_r)| AchievementDef {
+                key: String::from(*key),
+                name: String::from(*name),
+                description: String::from(*description),
+                stat: *stat,
+                goal: *goal,
+                per_life: *per_life,
+                min_r: *min_r,
+            },
+        )
+        .collect()
+}
+
+struct Progress {
+    // value in the current life
+    life: f64,
+    // best life for per life achievements and measured stats, total for the other counters
+    value: f64,
+    // milliseconds since the unix epoch
+    unlocked_at: Option<f64>,
+}
+
+pub struct Achievements {
+    defs: Vec<AchievementDef>,
+    progress: Vec<Progress>,
+    // uid of the player sphere whose events count
+    uid: Option<usize>,
+    // [count, index, index, ...] of achievements unlocked by the last handled events
+    unlocked: Vec<usize>,
+}
+
+impl Achievements {
+    pub fn new(defs: Vec<AchievementDef>) -> Achievements {
+        let mut achievements = Achievements {
+            defs: vec![],
+            progress: vec![],
+            uid: None,
+            unlocked: vec![0],
+        };
+
+        for def in defs {
+            achievements.insert(def);
+        }
+
+        return achievements;
+    }
+
+    // a definition with the same key replaces the old one and keeps its progress,
+    // keys are one field of a serialize() line, so they cannot hold a tab or a line break
+    pub fn add(&mut self, def: AchievementDef) -> Result<(), ApiError> {
+        if def.key.contains(['\t', '\n', '\r']) {
+            return Err(ApiError::BADKEY);
+        }
+
+        self.insert(def);
+
+        Ok(())
+    }
+
+    fn insert(&mut self, def: AchievementDef) {
+        match self.defs.iter().position(|d| d.key == def.key) {
+            Some(index) => self.defs[index] = def,
+            None => {
+                self.defs.push(def);
+                self.progress.push(Progress {
+                    life: 0.0,
+                    value: 0.0,
+                    unlocked_at: None,
+                });
+            }
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.defs.clear();
+        self.progress.clear();
+        self.unlocked.clear();
+        self.unlocked.push(0);
+    }
+
+    pub fn set_player(&mut self, uid: Option<usize>) {
+        self.uid = uid;
+
+        for progress in self.progress.iter_mut() {
+            progress.life = 0.0;
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.defs.len()
+    }
+
+    pub fn get_def(&self, index: usize) -> Option<&AchievementDef> {
+        self.defs.get(index)
+    }
+
+    pub fn get_progress(&self, index: usize) -> f64 {
+        match self.progress.get(index) {
+            Some(progress) => progress.value,
+            None => 0.0,
+        }
+    }
+
+    pub fn get_unlocked_at(&self, index: usize) -> Option<f64> {
+        self.progress.get(index)?.unlocked_at
+    }
+
+    pub fn get_unlocked(&self) -> &Vec<usize> {
+        &self.unlocked
+    }
+
+    // call once per update with all the events of the update, now is the unlock date
+    pub fn handle_events(&mut self, events: &[WorldEvent], now: f64) {
+        self.unlocked.clear();
+        self.unlocked.push(0);
+
+        let uid = match self.uid {
+            Some(uid) => uid,
+            None => return,
+        };
+
+        for event in events.iter() {
+            match event {
+                WorldEvent::Stats {
+                    uid: u,
+                    score,
+                    time_alive,
+                    r,
+                } if *u == uid => {
+                    self.measure(AchievementStat::SCORE, *score as f64, *r);
+                    self.measure(AchievementStat::TIMEALIVE, *time_alive, *r);
+                }
+                WorldEvent::Kill {
+                    killer_uid,
+                    killer_r,
+                    by_bullet,
+                    ..
+                } if *killer_uid == uid => {
+                    if *by_bullet {
+                        self.count(AchievementStat::BULLETKILLS, *killer_r);
+                    } else {
+                        self.count(AchievementStat::EATS, *killer_r);
+                    }
+                }
+                WorldEvent::AmEscape { uid: u, r } if *u == uid => {
+                    self.count(AchievementStat::AMESCAPES, *r);
+                }
+                WorldEvent::Death { uid: u, .. } if *u == uid => {
+                    self.set_player(None);
+                }
+                _ => {}
+            }
+        }
+
+        for i in 0..self.defs.len() {
+            let progress = &mut self.progress[i];
+
+            if progress.unlocked_at.is_none() && progress.value >= self.defs[i].goal {
+                progress.unlocked_at = Some(now);
+                self.unlocked.push(i);
+            }
+        }
+
+        self.unlocked[0] = self.unlocked.len() - 1;
+    }
+
+    fn count(&mut self, stat: AchievementStat, r: f64) {
+        for (def, progress) in self.defs.iter().zip(self.progress.iter_mut()) {
+            if def.stat != stat || r < def.min_r {
+                continue;
+            }
+
+            progress.life += 1.0;
+
+            if def.per_life {
+                progress.value = f64::max(progress.value, progress.life);
+            } else {
+                progress.value += 1.0;
+            }
+        }
+    }
+
+    fn measure(&mut self, stat: AchievementStat, value: f64, r: f64) {
+        for (def, progress) in self.defs.iter().zip(self.progress.iter_mut()) {
+            if def.stat != stat || r < def.min_r {
+                continue;
+            }
+
+            progress.life = value;
+            progress.value = f64::max(progress.value, value);
+        }
+    }
+
+    // one "key \t value \t unlocked_at" line per achievement, unlocked_at is empty if locked
+    pub fn serialize(&self) -> String {
+        let mut out = String::from(BLOB_HEADER);
+        out.push('\n');
+
+        for (def, progress) in self.defs.iter().zip(self.progress.iter()) {
+            let unlocked_at = match progress.unlocked_at {
+                Some(date) => date.to_string(),
+                None => String::new(),
+            };
+
+            out.push_str(&format!(
+                "{}\t{}\t{}\n",
+                def.key, progress.value, unlocked_at
+            ));
+        }
+
+        return out;
+    }
+
+    // restores the progress of the known definitions, unknown keys and broken lines are skipped
+    pub fn deserialize(&mut self, blob: &str) {
+        let mut lines = blob.lines();
+
+        if lines.next() != Some(BLOB_HEADER) {
+            return;
+        }
+
+        let indices: HashMap<&str, usize> = self
+            .defs
+            .iter()
+            .enumerate()
+            .map(|(i, def)| (def.key.as_str(), i))
+            .collect();
+
+        for line in lines {
+            let mut parts = line.split('\t');
+
+            let index = match parts.next().and_then(|key| indices.get(key)) {
+                Some(index) => *index,
+                None => continue,
+            };
+
+            let value = match parts.next().and_then(|v| v.parse().ok()) {
+                Some(value) => value,
+                None => continue,
+            };
+
+            let unlocked_at = parts.next().and_then(|v| v.parse().ok());
+
+            let progress = &mut self.progress[index];
+            progress.value = value;
+            progress.unlocked_at = unlocked_at;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{AchievementDef, AchievementStat, Achievements};
+    use crate::{error::ApiError, events::WorldEvent};
+
+    fn def(key: &str, stat: AchievementStat, goal: f64, per_life: bool) -> AchievementDef {
+        AchievementDef::new(
+            String::from(key),
+            String::from(key),
+            String::new(),
+            stat,
+            goal,
+            per_life,
+            0.0,
+        )
+        .unwrap()
+    }
+
+    fn eat(uid: usize) -> WorldEvent {
+        WorldEvent::Kill {
+            killer_uid: uid,
+            victim_uid: 100,
+            killer_r: 10.0,
+            by_bullet: false,
+        }
+    }
+
+    fn death(uid: usize) -> WorldEvent {
+        WorldEvent::Death {
+            uid,
+            is_fake: false,
+            name: String::new(),
+            peak_score: 0,
+            time_alive: 0.0,
+            kills: 0,
+        }
+    }
+
+    #[test]
+    fn unlocks_once_when_the_goal_is_reached() {
+        let mut achievements = Achievements::new(vec![
+            def("score", AchievementStat::SCORE, 100.0, true),
+            def("eats", AchievementStat::EATS, 2.0, false),
+        ]);
+        achievements.set_player(Some(1));
+
+        achievements.handle_events(&[eat(1), eat(2)], 1.0);
+
+        assert_eq!(achievements.get_unlocked(), &vec![0]);
+
+        achievements.handle_events(&[eat(1)], 2.0);
+
+        assert_eq!(achievements.get_unlocked(), &vec![1, 1]);
+        assert_eq!(achievements.get_unlocked_at(1), Some(2.0));
+        assert_eq!(achievements.get_unlocked_at(0), None);
+
+        // already unlocked, not reported again
+        achievements.handle_events(&[eat(1)], 3.0);
+
+        assert_eq!(achievements.get_unlocked(), &vec![0]);
+        assert_eq!(achievements.get_unlocked_at(1), Some(2.0));
+    }
+
+    #[test]
+    fn per_life_counters_reset_on_death() {
+        let mut achievements = Achievements::new(vec![
+            def("life", AchievementStat::EATS, 3.0, true),
+            def("total", AchievementStat::EATS, 3.0, false),
+        ]);
+
+        achievements.set_player(Some(1));
+        achievements.handle_events(&[eat(1), eat(1), death(1)], 1.0);
+        achievements.set_player(Some(2));
+        achievements.handle_events(&[eat(2)], 2.0);
+
+        // the best life had 2, the total is 3
+        assert_eq!(achievements.get_progress(0), 2.0);
+        assert_eq!(achievements.get_progress(1), 3.0);
+        assert_eq!(achievements.get_unlocked(), &vec![1, 1]);
+
+        // events of the dead sphere do not count anymore
+        achievements.handle_events(&[eat(1), eat(1)], 3.0);
+
+        assert_eq!(achievements.get_progress(0), 2.0);
+    }
+
+    #[test]
+    fn clear_drops_the_definitions_and_the_unlocked_list() {
+        let mut achievements =
+            Achievements::new(vec![def("eats", AchievementStat::EATS, 1.0, false)]);
+        achievements.set_player(Some(1));
+        achievements.handle_events(&[eat(1)], 1.0);
+
+        assert_eq!(achievements.get_unlocked(), &vec![1, 0]);
+
+        achievements.clear();
+
+        assert_eq!(achievements.len(), 0);
+        assert_eq!(achievements.get_unlocked(), &vec![0]);
+    }
+
+    #[test]
+    fn rejects_keys_that_break_the_blob() {
+        let mut achievements = Achievements::new(vec![]);
+
+        for key in ["a\tb", "a\nb", "a\r"] {
+            let result = achievements.add(def(key, AchievementStat::EATS, 1.0, false));
+
+            assert_eq!(result, Err(ApiError::BADKEY));
+        }
+
+        assert_eq!(achievements.len(), 0);
+    }
+
+    #[test]
+    fn progress_round_trips() {
+        let defs = vec![
+            def("eats", AchievementStat::EATS, 1.0, false),
+            def("score", AchievementStat::SCORE, 100.0, true),
+        ];
+        let mut achievements = Achievements::new(defs.clone());
+        achievements.set_player(Some(1));
+        achievements.handle_events(&[eat(1)], 1234.5);
+
+        let mut loaded = Achievements::new(defs);
+        loaded.deserialize(&achievements.serialize());
+
+        assert_eq!(loaded.get_progress(0), 1.0);
+        assert_eq!(loaded.get_unlocked_at(0), Some(1234.5));
+        assert_eq!(loaded.get_progress(1), 0.0);
+        assert_eq!(loaded.get_unlocked_at(1), None);
+    }
+
+    #[test]
+    fn rejects_bad_goals() {
+        let new = |goal: f64, min_r: f64| {
+            AchievementDef::new(
+                String::from("a"),
+                String::from("a"),
+                String::new(),
+                AchievementStat::EATS,
+                goal,
+                false,
+                min_r,
+            )
+            .err()
+        };
+
+        assert!(new(f64::NAN, 0.0) == Some(ApiError::NONFINITE));
+        assert!(new(-1.0, 0.0) == Some(ApiError::NEGATIVE));
+        assert!(new(1.0, f64::INFINITY) == Some(ApiError::NONFINITE));
+        assert!(new(1.0, -5.0) == Some(ApiError::NEGATIVE));
+    }
+}
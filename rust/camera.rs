@@ -0,0 +1,186 @@
+// The view into the world. The camera eases toward a target position and view area,
+// keeps the view inside the world bounds, can frame several spheres at once, and converts
+// between screen pixels and world coordinates.
+
+use crate::{
+    config::Topology,
+    constants::{
+        CAMERA_BOUNDS_MARGIN, CAMERA_FOLLOW_RATE, CAMERA_FRAME_MARGIN, CAMERA_VIEW_AREA_MIN,
+        CAMERA_VIEW_AREA_PER_R, CAMERA_ZOOM_RATE,
+    },
+    pool::Handle,
+    utils::get_view_size,
+    world::World,
+};
+
+pub struct Camera {
+    // center of the view
+    pub x: f64,
+    pub y: f64,
+    pub view_area: f64,
+    pub target_x: f64,
+    pub target_y: f64,
+    pub target_view_area: f64,
+    // how fast the position and the view area catch up with the target (per second)
+    pub follow_rate: f64,
+    pub zoom_rate: f64,
+    // keep the view inside a bounded or circular world, up to CAMERA_BOUNDS_MARGIN past the walls
+    pub clamp_to_world: bool,
+    screen_width: f64,
+    screen_height: f64,
+    // jump to the target on the next update instead of easing
+    snap: bool,
+}
+
+impl Camera {
+    pub fn new(x: f64, y: f64, view_area: f64) -> Camera {
+        Camera {
+            x,
+            y,
+            view_area,
+            target_x: x,
+            target_y: y,
+            target_view_area: view_area,
+            follow_rate: CAMERA_FOLLOW_RATE,
+            zoom_rate: CAMERA_ZOOM_RATE,
+            clamp_to_world: true,
+            screen_width: 1.0,
+            screen_height: 1.0,
+            snap: true,
+        }
+    }
+
+    // view area of a sphere with radius r, bigger spheres see more
+    pub fn get_sphere_view_area(r: f64) -> f64 {
+        r * CAMERA_VIEW_AREA_PER_R + CAMERA_VIEW_AREA_MIN
+    }
+
+    // size of the screen in pixels, the view has the same aspect ratio
+    pub fn set_screen(&mut self, width: f64, height: f64) {
+        self.screen_width = f64::max(width, 1.0);
+        self.screen_height = f64::max(height, 1.0);
+    }
+
+    pub fn get_aspect_ratio(&self) -> f64 {
+        self.screen_width / self.screen_height
+    }
+
+    pub fn look_at(&mut self, x: f64, y: f64, view_area: f64) {
+        self.target_x = x;
+        self.target_y = y;
+        self.target_view_area = view_area;
+    }
+
+    pub fn snap(&mut self) {
+        self.snap = true;
+    }
+
+    // target the smallest view that fits all the spheres, but not smaller than the view of
+    // the biggest one. returns false if none of them is alive
+    pub fn frame(&mut self, world: &World, spheres: &[Handle]) -> bool {
+        let mut origin: Option<(f64, f64)> = None;
+        let (mut left, mut top, mut right, mut bottom) = (0.0, 0.0, 0.0, 0.0);
+        let mut max_r: f64 = 0.0;
+
+        for handle in spheres.iter() {
+            let sphere = match world.spheres.get(*handle) {
+                Some(sphere) if sphere.r > 0.0 => sphere,
+                _ => continue,
+            };
+
+            // measured from the first sphere, so a toroidal world uses the nearest copies
+            let (ox, oy) = *origin.get_or_insert((sphere.x, sphere.y));
+            let dx = world.delta(ox, sphere.x);
+            let dy = world.delta(oy, sphere.y);
+
+            left = f64::min(left, dx - sphere.r);
+            right = f64::max(right, dx + sphere.r);
+            top = f64::min(top, dy - sphere.r);
+            bottom = f64::max(bottom, dy + sphere.r);
+            max_r = f64::max(max_r, sphere.r);
+        }
+
+        let (ox, oy) = match origin {
+            Some(origin) => origin,
+            None => return false,
+        };
+
+        let ar = self.get_aspect_ratio();
+        let w = f64::max(right - left, (bottom - top) * ar) * CAMERA_FRAME_MARGIN;
+
+        self.look_at(
+            ox + (left + right) * 0.5,
+            oy + (top + bottom) * 0.5,
+            f64::max(w * w / ar, Camera::get_sphere_view_area(max_r)),
+        );
+
+        return true;
+    }
+
+    pub fn update(&mut self, world: &World, dt: f64) {
+        if self.snap {
+            self.x = self.target_x;
+            self.y = self.target_y;
+            self.view_area = self.target_view_area;
+            self.snap = false;
+        } else {
+            let t = 1.0 - f64::exp(-self.follow_rate * dt);
+            let zoom_t = 1.0 - f64::exp(-self.zoom_rate * dt);
+
+            self.x += world.delta(self.x, self.target_x) * t;
+            self.y += world.delta(self.y, self.target_y) * t;
+            self.view_area += (self.target_view_area - self.view_area) * zoom_t;
+        }
+
+        match world.config.topology {
+            Topology::TOROIDAL => {
+                self.x = f64::rem_euclid(self.x, world.size);
+                self.y = f64::rem_euclid(self.y, world.size);
+            }
+            Topology::BOUNDED | Topology::CIRCULAR => {
+                if self.clamp_to_world {
+                    let (w, h) = self.get_size();
+
+                    self.x = Camera::clamp_axis(self.x, w * 0.5, world.size);
+                    self.y = Camera::clamp_axis(self.y, h * 0.5, world.size);
+                }
+            }
+        }
+    }
+
+    // center the view when it is wider than the world
+    fn clamp_axis(v: f64, half: f64, size: f64) -> f64 {
+        let min = half - CAMERA_BOUNDS_MARGIN;
+        let max = size - half + CAMERA_BOUNDS_MARGIN;
+
+        if min > max {
+            return size * 0.5;
+        }
+
+        return f64::clamp(v, min, max);
+    }
+
+    // (width, height) of the view in world units
+    pub fn get_size(&self) -> (f64, f64) {
+        get_view_size(self.get_aspect_ratio(), self.view_area)
+    }
+
+    pub fn screen_to_world(&self, sx: f64, sy: f64) -> (f64, f64) {
+        let (w, h) = self.get_size();
+
+        return (
+            self.x + (sx / self.screen_width - 0.5) * w,
+            self.y + (sy / self.screen_height - 0.5) * h,
+        );
+    }
+
+    // in a toroidal world pass the position of the nearest copy
+    pub fn world_to_screen(&self, x: f64, y: f64) -> (f64, f64) {
+        let (w, h) = self.get_size();
+
+        return (
+            ((x - self.x) / w + 0.5) * self.screen_width,
+            ((y - self.y) / h + 0.5) * self.screen_height,
+        );
+    }
+}
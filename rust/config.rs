@@ -0,0 +1,173 @@
+use crate::{
+    constants::{MAX_SPHERE_SPEED, STARTING_PLAYER_R},
+    error::{check_finite, check_positive, ApiError},
+    sphere::SphereType,
+};
+use wasm_bindgen::prelude::*;
+
+#[wasm_bindgen]
+#[repr(u8)]
+#[derive(PartialEq, Clone, Copy)]
+pub enum Topology {
+    // spheres bounce off the world edges
+    BOUNDED = 0,
+    // spheres leave one edge and come back on the opposite edge
+    TOROIDAL = 1,
+    // spheres bounce off a circle that fits inside the world square
+    CIRCULAR = 2,
+}
+
+#[wasm_bindgen]
+#[repr(u8)]
+#[derive(PartialEq, Clone, Copy)]
+pub enum PhysicsModel {
+    // absorbing only changes radii and shooting pushes with a fixed SHOOT_FORCE
+    CLASSIC = 0,
+    // area is used as mass, absorbing and shooting conserve momentum
+    MOMENTUM = 1,
+}
+
+#[wasm_bindgen]
+#[repr(u8)]
+#[derive(PartialEq, Clone, Copy)]
+pub enum ControlScheme {
+    // players move by shooting, which costs mass
+    SHOOT = 0,
+    // players steer toward a target point (classic mode), shooting still works
+    MOVE = 1,
+}
+
+// which MotionProfile a sphere uses, fake players (bots) have their own
+#[wasm_bindgen]
+#[repr(u8)]
+#[derive(PartialEq, Clone, Copy)]
+pub enum MotionKind {
+    PLAYER = 0,
+    BOT = 1,
+    FOOD = 2,
+    AM = 3,
+    BULLET = 4,
+    VIRUS = 5,
+}
+
+// VIRUS is the last kind, update this when adding one
+const MOTION_KIND_LEN: usize = MotionKind::VIRUS as usize + 1;
+
+#[wasm_bindgen]
+#[derive(Clone, Copy)]
+pub struct MotionProfile {
+    // speed lost per second, relative to the speed
+    pub linear_drag: f64,
+    // speed lost per second, relative to the speed squared
+    pub quadratic_drag: f64,
+    // top speed of a sphere with radius STARTING_PLAYER_R, under PhysicsModel::MOMENTUM
+    // it is only the speed the MOVE control steers for
+    pub max_speed: f64,
+    // top speed = max_speed * (STARTING_PLAYER_R / r) ^ max_speed_exponent,
+    // 0 gives every size the same top speed
+    pub max_speed_exponent: f64,
+}
+
+#[wasm_bindgen]
+impl MotionProfile {
+    pub fn new(
+        linear_drag: f64,
+        quadratic_drag: f64,
+        max_speed: f64,
+        max_speed_exponent: f64,
+    ) -> Result<MotionProfile, ApiError> {
+        check_positive(&[linear_drag, quadratic_drag, max_speed])?;
+        check_finite(&[max_speed_exponent])?;
+
+        return Ok(MotionProfile {
+            linear_drag,
+            quadratic_drag,
+            max_speed,
+            max_speed_exponent,
+        });
+    }
+
+    // no drag and no speed limit
+    pub fn free() -> MotionProfile {
+        MotionProfile::with_max_speed(f64::INFINITY)
+    }
+
+    pub fn get_max_speed(&self, r: f64) -> f64 {
+        if self.max_speed_exponent == 0.0 || r <= 0.0 {
+            return self.max_speed;
+        }
+
+        self.max_speed * f64::powf(STARTING_PLAYER_R / r, self.max_speed_exponent)
+    }
+}
+
+impl MotionProfile {
+    // no drag and the same top speed for every size
+    fn with_max_speed(max_speed: f64) -> MotionProfile {
+        MotionProfile {
+            linear_drag: 0.0,
+            quadratic_drag: 0.0,
+            max_speed,
+            max_speed_exponent: 0.0,
+        }
+    }
+}
+
+#[wasm_bindgen]
+#[derive(Clone, Copy)]
+pub struct WorldConfig {
+    pub topology: Topology,
+    // grow or shrink the world gradually with the number of live players
+    pub dynamic_size: bool,
+    pub physics: PhysicsModel,
+    pub control: ControlScheme,
+    // freeze the sectors with no real player around, for big worlds on a server
+    pub sleep: bool,
+    #[wasm_bindgen(skip)]
+    pub motion: [MotionProfile; MOTION_KIND_LEN],
+}
+
+#[wasm_bindgen]
+impl WorldConfig {
+    pub fn new() -> WorldConfig {
+        WorldConfig {
+            topology: Topology::BOUNDED,
+            dynamic_size: false,
+            physics: PhysicsModel::CLASSIC,
+            control: ControlScheme::SHOOT,
+            sleep: false,
+            // players can not go faster than a shot already allows, the rest move freely
+            motion: [
+                MotionProfile::with_max_speed(MAX_SPHERE_SPEED),
+                MotionProfile::with_max_speed(MAX_SPHERE_SPEED),
+                MotionProfile::free(),
+                MotionProfile::free(),
+                MotionProfile::free(),
+                MotionProfile::free(),
+            ],
+        }
+    }
+
+    pub fn set_motion_profile(&mut self, kind: MotionKind, profile: MotionProfile) {
+        self.motion[kind as usize] = profile;
+    }
+
+    pub fn get_motion_profile(&self, kind: MotionKind) -> MotionProfile {
+        self.motion[kind as usize]
+    }
+}
+
+impl WorldConfig {
+    pub fn get_sphere_motion_profile(&self, r#type: SphereType, is_fake: bool) -> &MotionProfile {
+        let kind = match r#type {
+            SphereType::PLAYER if is_fake => MotionKind::BOT,
+            SphereType::PLAYER => MotionKind::PLAYER,
+            SphereType::FOOD => MotionKind::FOOD,
+            SphereType::AM => MotionKind::AM,
+            SphereType::BULLET => MotionKind::BULLET,
+            SphereType::VIRUS => MotionKind::VIRUS,
+        };
+
+        &self.motion[kind as usize]
+    }
+}
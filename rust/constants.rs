@@ -1,25 +1,105 @@
+use crate::sphere::SphereType;
+
 pub static WORLD_SIZE: f64 = 1000.0;
+pub static WORLD_RADIUS: f64 = 500.0;
+pub static WORLD_SIZE_MIN: f64 = 500.0;
+pub static WORLD_SIZE_MAX: f64 = 2000.0;
+pub static WORLD_AREA_PER_PLAYER: f64 = 10000.0;
+pub static WORLD_RESIZE_SPEED: f64 = 5.0;
 pub static MAX_SPHERE_R: f64 = 250.0;
 pub static FOOD_SPAWN_R_MIN: f64 = 1.0;
 pub static FOOD_SPAWN_R_MAX: f64 = 5.0;
 pub static AM_SPAWN_R_MIN: f64 = 1.0;
 pub static AM_SPAWN_R_MAX: f64 = 20.0;
+pub static VIRUS_SPAWN_R_MIN: f64 = 10.0;
+pub static VIRUS_SPAWN_R_MAX: f64 = 15.0;
 pub static STARTING_PLAYER_R: f64 = 5.0;
 pub static STARTING_PLAYER_R_RANDOMNESS: f64 = 1.0;
 pub static MAX_SPHERE_SPEED: f64 = 100.0;
 pub static FOOD_SPAWN_DELAY: f64 = 0.1;
 pub static AM_SPAWN_DELAY: f64 = 1.0;
+pub static VIRUS_SPAWN_DELAY: f64 = 5.0;
+// longest part of an update the spawns catch up on, a stalled server does not spawn
+// a burst of spheres on its next update
+pub static MAX_SPAWN_CATCH_UP: f64 = 0.25;
+// random positions tried for a player before giving up, obstacles can cover most of the world
+pub static MAX_SPAWN_ATTEMPTS: usize = 100;
+// spawn budget of a world of WORLD_SIZE, split between the sectors by area
 pub static MAX_SPHERE_COUNT: usize = 1000;
+// hard limit of the sphere pool for a world of WORLD_SIZE (bigger worlds get more by area),
+// bullets and burst pieces are not spawned past it
+pub static MAX_SPHERE_CAPACITY: usize = 8192;
+// largest width and height of a sector
+pub static SECTOR_SIZE: f64 = 500.0;
+// sectors around the one of a real player that stay awake, in every direction
+pub static SECTOR_WAKE_DISTANCE: usize = 1;
+// number of SphereType variants, VIRUS is the last type, update this when adding one
+pub static SPHERE_TYPE_COUNT: usize = SphereType::VIRUS as usize + 1;
 pub static SPHERE_COLOR_MIN: u32 = 0x66;
 pub static SPHERE_COLOR_MAX: u32 = 0xcc;
 pub static R_DECREASE_RATIO: f64 = 0.001;
 
+// spheres bigger than this burst when they touch a virus, smaller ones can hide behind it
+pub static VIRUS_BURST_MIN_R: f64 = 30.0;
+pub static VIRUS_BURST_AREA_RATIO: f64 = 0.5;
+pub static VIRUS_BURST_PIECES: usize = 8;
+pub static VIRUS_BURST_SPEED: f64 = 80.0;
+pub static VIRUS_COLOR: u32 = 0x7fbf3f;
+
 pub static SHOOT_DELAY: f64 = 0.1;
 pub static SHOOT_AREA_RATIO_SQ: f64 = 0.98;
 pub static SHOOT_AREA_RATIO: f64 = 0.98994949366;
 pub static BULLET_AREA_RATIO: f64 = 0.14142135623;
 pub static SHOOT_FORCE: f64 = 5.0;
 pub static BULLET_SPEED: f64 = 120.0;
+// a shooter gets the kill if the sphere its bullet hit dies within this many seconds
+pub static BULLET_HIT_CREDIT_TIME: f64 = 3.0;
+
+// used by ControlScheme::MOVE, the acceleration of a sphere with radius STARTING_PLAYER_R,
+// bigger spheres accelerate slower by (STARTING_PLAYER_R / r) ^ MOVE_ACCELERATION_EXPONENT
+pub static MOVE_ACCELERATION: f64 = 200.0;
+pub static MOVE_ACCELERATION_EXPONENT: f64 = 0.5;
+// spheres slow down when they are closer than this to the target
+pub static MOVE_SLOW_RADIUS: f64 = 20.0;
+
+// how fast the client prediction error fades out (per second),
+// and how far off it can be before the sphere just snaps to the corrected position
+pub static PREDICTION_CORRECTION_RATE: f64 = 10.0;
+pub static PREDICTION_SNAP_DISTANCE: f64 = 50.0;
+
+// view area of a sphere is CAMERA_VIEW_AREA_PER_R * r + CAMERA_VIEW_AREA_MIN
+pub static CAMERA_VIEW_AREA_MIN: f64 = 25000.0;
+pub static CAMERA_VIEW_AREA_PER_R: f64 = 2500.0;
+// how fast the camera catches up with its target position and view area (per second)
+pub static CAMERA_FOLLOW_RATE: f64 = 8.0;
+pub static CAMERA_ZOOM_RATE: f64 = 2.0;
+// how far past the walls the camera can look
+pub static CAMERA_BOUNDS_MARGIN: f64 = 20.0;
+// space around the spheres when the camera frames several of them
+pub static CAMERA_FRAME_MARGIN: f64 = 1.5;
+
+pub static SPECTATOR_FREE_VIEW_AREA: f64 = 50000.0;
+// the auto director keeps a grid of recent collisions, every collision adds 1 to its cell
+// and the cells fade by DIRECTOR_ACTIVITY_DECAY per second
+pub static DIRECTOR_ACTIVITY_CELLS: usize = 16;
+pub static DIRECTOR_ACTIVITY_DECAY: f64 = 0.5;
+// how much one recent collision is worth compared to one unit of area (r * r)
+pub static DIRECTOR_ACTIVITY_WEIGHT: f64 = 200.0;
+// the mass of players closer than this counts toward the score of a spot
+pub static DIRECTOR_NEARBY_RADIUS: f64 = 150.0;
+// the director watches a player at least this long, and only switches to a spot
+// that scores DIRECTOR_SWITCH_RATIO times better
+pub static DIRECTOR_MIN_HOLD_TIME: f64 = 4.0;
+pub static DIRECTOR_SWITCH_RATIO: f64 = 1.5;
+
+pub static MINIMAP_RESOLUTION: usize = 32;
+// the minimap buffer grows with the square of the resolution
+pub static MAX_MINIMAP_RESOLUTION: usize = 256;
+
+// spheres per thread below which the work stays on fewer threads
+pub static PARALLEL_MIN_CHUNK_LEN: usize = 256;
+
+pub static MAX_HIGHSCORE_ENTRIES: usize = 100;
 
 pub static FAKE_NAME_LEN: usize = 150;
 
@@ -0,0 +1,79 @@
+// Errors of the JS API. Input from JS is checked before it reaches the world, a bad value
+// throws one of these as a JS exception instead of panicking the wasm module.
+// Native callers get them as plain values.
+
+use std::fmt;
+use wasm_bindgen::{JsError, JsValue};
+
+#[derive(PartialEq, Clone, Copy, Debug)]
+pub enum ApiError {
+    // a number is NaN or infinite
+    NONFINITE,
+    // a time step or a size is below zero
+    NEGATIVE,
+    // a number that is divided by, or a count, is zero
+    ZERO,
+    // a direction of length zero
+    NODIRECTION,
+    // the flattened points of a polygon are not (x, y) pairs
+    BADPOLYGON,
+    // an achievement key holds a tab or a newline
+    BADKEY,
+    // the viewer pool is at its max size
+    FULL,
+}
+
+impl fmt::Display for ApiError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let message = match self {
+            ApiError::NONFINITE => "number is NaN or infinite",
+            ApiError::NEGATIVE => "number is below zero",
+            ApiError::ZERO => "number is zero",
+            ApiError::NODIRECTION => "direction has length zero",
+            ApiError::BADPOLYGON => "polygon points are not (x, y) pairs",
+            ApiError::BADKEY => "achievement key holds a tab or a newline",
+            ApiError::FULL => "no room for another viewer",
+        };
+
+        return write!(f, "{}", message);
+    }
+}
+
+impl std::error::Error for ApiError {}
+
+// only called by the generated bindings, so only on wasm
+impl From<ApiError> for JsValue {
+    fn from(error: ApiError) -> JsValue {
+        JsError::from(error).into()
+    }
+}
+
+pub fn check_finite(values: &[f64]) -> Result<(), ApiError> {
+    if values.iter().all(|value| value.is_finite()) {
+        return Ok(());
+    }
+
+    return Err(ApiError::NONFINITE);
+}
+
+// finite and at least zero, for time steps and sizes
+pub fn check_positive(values: &[f64]) -> Result<(), ApiError> {
+    check_finite(values)?;
+
+    if values.iter().all(|value| *value >= 0.0) {
+        return Ok(());
+    }
+
+    return Err(ApiError::NEGATIVE);
+}
+
+// finite and above zero, for the values that are divided by
+pub fn check_above_zero(values: &[f64]) -> Result<(), ApiError> {
+    check_positive(values)?;
+
+    if values.iter().all(|value| *value > 0.0) {
+        return Ok(());
+    }
+
+    return Err(ApiError::ZERO);
+}
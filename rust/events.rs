@@ -0,0 +1,36 @@
+// Things that happened during the last World::update, for systems built on top of
+// the world (high scores, ...). The list is cleared at the start of every update.
+
+pub enum WorldEvent {
+    // a player sphere killed another player sphere, either by eating it or by hitting it
+    // with a bullet shortly before it died
+    Kill {
+        killer_uid: usize,
+        victim_uid: usize,
+        // radius of the killer at the time of the kill
+        killer_r: f64,
+        by_bullet: bool,
+    },
+    // a player sphere stopped touching anti matter and survived
+    AmEscape {
+        uid: usize,
+        r: f64,
+    },
+    // current stats of every real player, sent every update
+    Stats {
+        uid: usize,
+        score: usize,
+        time_alive: f64,
+        r: f64,
+    },
+    // a player sphere died, with the stats of the life that just ended.
+    // fake players respawn with the same uid right after
+    Death {
+        uid: usize,
+        is_fake: bool,
+        name: String,
+        peak_score: usize,
+        time_alive: f64,
+        kills: usize,
+    },
+}
@@ -0,0 +1,283 @@
+// All time high score table. Every finished life of a real player is recorded,
+// the table keeps the best `max_entries` lives plus the best life of every name.
+// It serializes to a plain text blob, so JS can keep it in localStorage and the
+// native build can keep it in a file.
+
+use std::collections::HashMap;
+
+use wasm_bindgen::prelude::*;
+
+static BLOB_HEADER: &str = "ubur-highscores 1";
+
+#[wasm_bindgen]
+#[derive(Clone)]
+pub struct HighScoreEntry {
+    #[wasm_bindgen(getter_with_clone)]
+    pub name: String,
+    pub score: usize,
+    // seconds
+    pub time_alive: f64,
+    pub kills: usize,
+    // milliseconds since the unix epoch
+    pub date: f64,
+}
+
+impl HighScoreEntry {
+    // better score first, the older entry wins a tie
+    fn is_better_than(&self, other: &HighScoreEntry) -> bool {
+        self.score > other.score || (self.score == other.score && self.date < other.date)
+    }
+
+    // one tab separated line, the name goes last so it can contain anything but a newline
+    fn write(&self, prefix: &str, out: &mut String) {
+        let name: String = self
+            .name
+            .chars()
+            .map(|c| if c == '\n' || c == '\r' { ' ' } else { c })
+            .collect();
+
+        out.push_str(&format!(
+            "{}\t{}\t{}\t{}\t{}\t{}\n",
+            prefix, self.score, self.time_alive, self.kills, self.date, name
+        ));
+    }
+
+    fn read(line: &str) -> Option<(&str, HighScoreEntry)> {
+        let mut parts = line.splitn(6, '\t');
+
+        let prefix = parts.next()?;
+        let score = parts.next()?.parse().ok()?;
+        let time_alive = parts.next()?.parse().ok()?;
+        let kills = parts.next()?.parse().ok()?;
+        let date = parts.next()?.parse().ok()?;
+        let name = String::from(parts.next()?);
+
+        Some((
+            prefix,
+            HighScoreEntry {
+                name,
+                score,
+                time_alive,
+                kills,
+                date,
+            },
+        ))
+    }
+}
+
+pub struct HighScoreTable {
+    // best first
+    entries: Vec<HighScoreEntry>,
+    personal_bests: HashMap<String, HighScoreEntry>,
+    max_entries: usize,
+}
+
+impl HighScoreTable {
+    pub fn new(max_entries: usize) -> HighScoreTable {
+        HighScoreTable {
+            entries: Vec::with_capacity(max_entries + 1),
+            personal_bests: HashMap::new(),
+            max_entries,
+        }
+    }
+
+    // returns the rank (starts from 1) of the entry, or None if it did not make the table
+    pub fn add(&mut self, entry: HighScoreEntry) -> Option<usize> {
+        self.add_personal_best(entry.clone());
+        self.add_to_table(entry)
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn get(&self, index: usize) -> Option<&HighScoreEntry> {
+        self.entries.get(index)
+    }
+
+    pub fn get_top(&self, n: usize) -> &[HighScoreEntry] {
+        &self.entries[0..usize::min(n, self.entries.len())]
+    }
+
+    pub fn get_personal_best(&self, name: &str) -> Option<&HighScoreEntry> {
+        self.personal_bests.get(name)
+    }
+
+    pub fn clear(&mut self) {
+        self.entries.clear();
+        self.personal_bests.clear();
+    }
+
+    pub fn serialize(&self) -> String {
+        let mut out = String::from(BLOB_HEADER);
+        out.push('\n');
+
+        for entry in self.entries.iter() {
+            entry.write("T", &mut out);
+        }
+
+        for entry in self.personal_bests.values() {
+            entry.write("P", &mut out);
+        }
+
+        return out;
+    }
+
+    // lines that can not be read are skipped, a blob from another format gives an empty table
+    pub fn deserialize(blob: &str, max_entries: usize) -> HighScoreTable {
+        let mut table = HighScoreTable::new(max_entries);
+        let mut lines = blob.lines();
+
+        if lines.next() != Some(BLOB_HEADER) {
+            return table;
+        }
+
+        for line in lines {
+            match HighScoreEntry::read(line) {
+                Some(("T", entry)) => {
+                    table.add_to_table(entry);
+                }
+                Some(("P", entry)) => {
+                    table.add_personal_best(entry);
+                }
+                _ => {}
+            }
+        }
+
+        return table;
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn save_to_file(&self, path: &std::path::Path) -> std::io::Result<()> {
+        std::fs::write(path, self.serialize())
+    }
+
+    // a missing file gives an empty table
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn load_from_file(
+        path: &std::path::Path,
+        max_entries: usize,
+    ) -> std::io::Result<HighScoreTable> {
+        match std::fs::read_to_string(path) {
+            Ok(blob) => Ok(HighScoreTable::deserialize(&blob, max_entries)),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+                Ok(HighScoreTable::new(max_entries))
+            }
+            Err(e) => Err(e),
+        }
+    }
+
+    fn add_to_table(&mut self, entry: HighScoreEntry) -> Option<usize> {
+        let index = self
+            .entries
+            .iter()
+            .position(|e| entry.is_better_than(e))
+            .unwrap_or(self.entries.len());
+
+        if index >= self.max_entries {
+            return None;
+        }
+
+        self.entries.insert(index, entry);
+        self.entries.truncate(self.max_entries);
+
+        Some(index + 1)
+    }
+
+    fn add_personal_best(&mut self, entry: HighScoreEntry) {
+        let is_better = match self.personal_bests.get(&entry.name) {
+            Some(best) => entry.is_better_than(best),
+            None => true,
+        };
+
+        if is_better {
+            self.personal_bests.insert(entry.name.clone(), entry);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{HighScoreEntry, HighScoreTable, BLOB_HEADER};
+
+    fn entry(name: &str, score: usize, date: f64) -> HighScoreEntry {
+        HighScoreEntry {
+            name: String::from(name),
+            score,
+            time_alive: 12.345,
+            kills: 3,
+            date,
+        }
+    }
+
+    fn assert_same(a: &HighScoreEntry, b: &HighScoreEntry) {
+        assert_eq!(a.name, b.name);
+        assert_eq!(a.score, b.score);
+        assert_eq!(a.time_alive, b.time_alive);
+        assert_eq!(a.kills, b.kills);
+        assert_eq!(a.date, b.date);
+    }
+
+    #[test]
+    fn serialize_round_trips() {
+        let mut table = HighScoreTable::new(3);
+        table.add(entry("ann", 50, 1.0));
+        table.add(entry("bob\twith a tab", 80, 2.0));
+        table.add(entry("ann", 70, 3.0));
+        table.add(entry("cid", 10, 4.0));
+        table.add(entry("dee", 60, 1700000000000.5));
+
+        let loaded = HighScoreTable::deserialize(&table.serialize(), 3);
+
+        assert_eq!(loaded.len(), 3);
+
+        for i in 0..3 {
+            assert_same(loaded.get(i).unwrap(), table.get(i).unwrap());
+        }
+
+        // personal bests also keep the names that fell out of the table
+        for name in ["ann", "bob\twith a tab", "cid", "dee"] {
+            assert_same(
+                loaded.get_personal_best(name).unwrap(),
+                table.get_personal_best(name).unwrap(),
+            );
+        }
+
+        assert_eq!(loaded.get_personal_best("ann").unwrap().score, 70);
+    }
+
+    #[test]
+    fn newlines_in_names_do_not_break_lines() {
+        let mut table = HighScoreTable::new(3);
+        table.add(entry("two\nlines\r", 50, 1.0));
+
+        let loaded = HighScoreTable::deserialize(&table.serialize(), 3);
+
+        assert_eq!(loaded.len(), 1);
+        assert_eq!(loaded.get(0).unwrap().name, "two lines ");
+    }
+
+    #[test]
+    fn malformed_lines_are_skipped() {
+        let blob = format!(
+            "{}\nT\t50\t1\t2\t3\tann\nT\tlots\t1\t2\t3\tbob\nT\t40\t1\nX\t30\t1\t2\t3\tcid\n\nP\t50\t1\t2\t3\tann\n",
+            BLOB_HEADER
+        );
+
+        let loaded = HighScoreTable::deserialize(&blob, 3);
+
+        assert_eq!(loaded.len(), 1);
+        assert_eq!(loaded.get(0).unwrap().name, "ann");
+        assert!(loaded.get_personal_best("ann").is_some());
+        assert!(loaded.get_personal_best("bob").is_none());
+        assert!(loaded.get_personal_best("cid").is_none());
+    }
+
+    #[test]
+    fn other_formats_give_an_empty_table() {
+        let loaded = HighScoreTable::deserialize("ubur-highscores 0\nT\t50\t1\t2\t3\tann\n", 3);
+
+        assert_eq!(loaded.len(), 0);
+        assert!(loaded.get_personal_best("ann").is_none());
+    }
+}
@@ -0,0 +1,205 @@
+use std::collections::HashMap;
+
+use wasm_bindgen::prelude::*;
+
+use crate::world::Command;
+
+// how many ticks ahead of the world an input can be stamped
+pub static MAX_INPUT_TICKS_AHEAD: u32 = 120;
+// how many inputs a player can queue for one tick, so a client can not grow the queue
+// without limit by sending more and more inputs for the same ticks
+pub static MAX_INPUTS_PER_TICK: usize = 4;
+
+#[wasm_bindgen]
+#[repr(u8)]
+#[derive(PartialEq, Clone, Copy, Debug)]
+pub enum InputError {
+    // the input targets a tick that is already processed
+    STALE = 0,
+    // the sequence number is not newer than the last accepted one
+    DUPLICATE = 1,
+    // the input targets a tick too far in the future
+    PREMATURE = 2,
+    // the sphere is dead or does not belong to the player anymore
+    UNOWNED = 3,
+    // the command has a NaN or infinite number, or a shot has no direction
+    INVALID = 4,
+    // the player already queued MAX_INPUTS_PER_TICK inputs for that tick
+    FLOOD = 5,
+}
+
+#[derive(Clone, Copy)]
+pub struct Input {
+    // uid of the player sphere that sent the input
+    pub uid: usize,
+    // per player sequence number, every new input must have a bigger one
+    pub seq: u32,
+    // the world tick this input applies to
+    pub tick: u32,
+    pub command: Command,
+}
+
+pub struct InputQueue {
+    pending: Vec<Input>,
+    // number of pending inputs of every (uid, tick)
+    pending_counts: HashMap<(usize, u32), usize>,
+    last_accepted_seq: HashMap<usize, u32>,
+    last_processed_seq: HashMap<usize, u32>,
+}
+
+impl InputQueue {
+    pub fn new() -> InputQueue {
+        InputQueue {
+            pending: vec![],
+            pending_counts: HashMap::new(),
+            last_accepted_seq: HashMap::new(),
+            last_processed_seq: HashMap::new(),
+        }
+    }
+
+    pub fn push(&mut self, input: Input, current_tick: u32) -> Result<(), InputError> {
+        if input.tick < current_tick {
+            return Err(InputError::STALE);
+        }
+
+        if input.tick > current_tick.saturating_add(MAX_INPUT_TICKS_AHEAD) {
+            return Err(InputError::PREMATURE);
+        }
+
+        if let Some(last) = self.last_accepted_seq.get(&input.uid) {
+            if input.seq <= *last {
+                return Err(InputError::DUPLICATE);
+            }
+        }
+
+        let count = self
+            .pending_counts
+            .entry((input.uid, input.tick))
+            .or_insert(0);
+
+        if *count >= MAX_INPUTS_PER_TICK {
+            return Err(InputError::FLOOD);
+        }
+
+        *count += 1;
+
+        self.last_accepted_seq.insert(input.uid, input.seq);
+        self.pending.push(input);
+
+        Ok(())
+    }
+
+    // sequence number for inputs created by the world itself (local play)
+    pub fn next_seq(&self, uid: usize) -> u32 {
+        match self.last_accepted_seq.get(&uid) {
+            Some(seq) => seq.wrapping_add(1),
+            None => 0,
+        }
+    }
+
+    // move the inputs that apply at or before the tick into out, ordered by (tick, uid, seq)
+    // so the result does not depend on the order they arrived in
+    pub fn take_due(&mut self, tick: u32, out: &mut Vec<Input>) {
+        out.clear();
+
+        let mut i = 0;
+
+        while i < self.pending.len() {
+            if self.pending[i].tick <= tick {
+                let input = self.pending.swap_remove(i);
+
+                self.pending_counts.remove(&(input.uid, input.tick));
+                out.push(input);
+            } else {
+                i += 1;
+            }
+        }
+
+        out.sort_by_key(|input| (input.tick, input.uid, input.seq));
+    }
+
+    pub fn mark_processed(&mut self, input: &Input) {
+        self.last_processed_seq.insert(input.uid, input.seq);
+    }
+
+    pub fn get_last_processed_seq(&self, uid: usize) -> Option<u32> {
+        self.last_processed_seq.get(&uid).copied()
+    }
+
+    // drop everything about a player that left the world
+    pub fn forget(&mut self, uid: usize) {
+        self.pending.retain(|input| input.uid != uid);
+        self.pending_counts.retain(|key, _| key.0 != uid);
+        self.last_accepted_seq.remove(&uid);
+        self.last_processed_seq.remove(&uid);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Input, InputError, InputQueue, MAX_INPUTS_PER_TICK, MAX_INPUT_TICKS_AHEAD};
+    use crate::{pool::Handle, world::Command};
+
+    fn input(uid: usize, seq: u32, tick: u32) -> Input {
+        Input {
+            uid,
+            seq,
+            tick,
+            command: Command::Move(Handle::INVALID, 0.0, 0.0),
+        }
+    }
+
+    #[test]
+    fn rejects_stale_inputs() {
+        let mut queue = InputQueue::new();
+
+        assert_eq!(queue.push(input(1, 0, 9), 10), Err(InputError::STALE));
+        assert_eq!(queue.push(input(1, 0, 10), 10), Ok(()));
+    }
+
+    #[test]
+    fn rejects_premature_inputs() {
+        let mut queue = InputQueue::new();
+        let last = 10 + MAX_INPUT_TICKS_AHEAD;
+
+        assert_eq!(
+            queue.push(input(1, 0, last + 1), 10),
+            Err(InputError::PREMATURE)
+        );
+        assert_eq!(queue.push(input(1, 0, last), 10), Ok(()));
+    }
+
+    #[test]
+    fn rejects_duplicate_sequence_numbers() {
+        let mut queue = InputQueue::new();
+
+        assert_eq!(queue.push(input(1, 5, 10), 10), Ok(()));
+        assert_eq!(queue.push(input(1, 5, 11), 10), Err(InputError::DUPLICATE));
+        assert_eq!(queue.push(input(1, 4, 11), 10), Err(InputError::DUPLICATE));
+        // other players have their own sequence
+        assert_eq!(queue.push(input(2, 5, 11), 10), Ok(()));
+    }
+
+    #[test]
+    fn limits_the_inputs_per_player_and_tick() {
+        let mut queue = InputQueue::new();
+        let mut seq = 0;
+
+        for _ in 0..MAX_INPUTS_PER_TICK {
+            assert_eq!(queue.push(input(1, seq, 10), 10), Ok(()));
+            seq += 1;
+        }
+
+        assert_eq!(queue.push(input(1, seq, 10), 10), Err(InputError::FLOOD));
+        // other ticks and other players are not affected
+        assert_eq!(queue.push(input(1, seq, 11), 10), Ok(()));
+        assert_eq!(queue.push(input(2, 0, 10), 10), Ok(()));
+
+        // the inputs of a processed tick stop counting
+        let mut due = vec![];
+        queue.take_due(10, &mut due);
+
+        assert_eq!(due.len(), MAX_INPUTS_PER_TICK + 1);
+        assert_eq!(queue.push(input(1, seq + 1, 11), 11), Ok(()));
+    }
+}
@@ -0,0 +1,133 @@
+// Per viewer visibility (interest management).
+// Each viewer keeps the set of spheres inside its view rectangle, found with the
+// quad tree instead of checking every sphere, and tracks which spheres entered or
+// left the view since its last update. A server can use one viewer per client to
+// only send nearby spheres, and a client can use several for multiple viewports.
+
+use crate::world::World;
+
+pub struct Viewer {
+    candidates: Vec<usize>,
+    // [count, id, id, ...]
+    ids: Vec<usize>,
+    // (offset x, offset y) for each id, only non zero in a toroidal world
+    offsets: Vec<f64>,
+    // sorted uids of the visible spheres, from this and the previous update
+    uids: Vec<usize>,
+    prev_uids: Vec<usize>,
+    // [count, id, id, ...] of spheres that became visible in the last update
+    entered_ids: Vec<usize>,
+    // [count, uid, uid, ...] of spheres that stopped being visible in the last update,
+    // uids are used because the sphere may be dead and its id reused
+    left_uids: Vec<usize>,
+}
+
+impl Viewer {
+    pub fn new() -> Viewer {
+        Viewer {
+            candidates: vec![],
+            ids: vec![0],
+            offsets: vec![],
+            uids: vec![],
+            prev_uids: vec![],
+            entered_ids: vec![0],
+            left_uids: vec![0],
+        }
+    }
+
+    pub fn reset(&mut self) {
+        self.ids.clear();
+        self.ids.push(0);
+        self.offsets.clear();
+        self.uids.clear();
+        self.prev_uids.clear();
+        self.entered_ids.clear();
+        self.entered_ids.push(0);
+        self.left_uids.clear();
+        self.left_uids.push(0);
+    }
+
+    // (x, y) is the center of the view
+    pub fn update(&mut self, world: &World, x: f64, y: f64, w: f64, h: f64) {
+        world.get_ids_in_region(x - w * 0.5, y - h * 0.5, w, h, &mut self.candidates);
+
+        // the quad tree returns an id once for every node it is in
+        self.candidates.sort_unstable();
+        self.candidates.dedup();
+
+        std::mem::swap(&mut self.uids, &mut self.prev_uids);
+        self.uids.clear();
+        self.ids.clear();
+        self.ids.push(0);
+        self.offsets.clear();
+
+        for id in self.candidates.iter() {
+            let sphere = world.spheres.at(*id);
+
+            if sphere.r <= 0.0 {
+                continue;
+            }
+
+            // in a toroidal world the nearest copy of the sphere is the one we see,
+            // so spheres across the seam show up as ghost copies near the edges
+            let dx = world.delta(x, sphere.x);
+            let dy = world.delta(y, sphere.y);
+
+            if f64::abs(dx) < w * 0.5 + sphere.r && f64::abs(dy) < h * 0.5 + sphere.r {
+                self.ids.push(*id);
+                self.offsets.push(x + dx - sphere.x);
+                self.offsets.push(y + dy - sphere.y);
+                self.uids.push(sphere.uid);
+            }
+        }
+
+        self.ids[0] = self.ids.len() - 1;
+        self.uids.sort_unstable();
+
+        // entered
+        self.entered_ids.clear();
+        self.entered_ids.push(0);
+
+        for i in 1..self.ids.len() {
+            let id = self.ids[i];
+
+            if self
+                .prev_uids
+                .binary_search(&world.spheres.at(id).uid)
+                .is_err()
+            {
+                self.entered_ids.push(id);
+            }
+        }
+
+        self.entered_ids[0] = self.entered_ids.len() - 1;
+
+        // left
+        self.left_uids.clear();
+        self.left_uids.push(0);
+
+        for uid in self.prev_uids.iter() {
+            if self.uids.binary_search(uid).is_err() {
+                self.left_uids.push(*uid);
+            }
+        }
+
+        self.left_uids[0] = self.left_uids.len() - 1;
+    }
+
+    pub fn get_ids(&self) -> &Vec<usize> {
+        &self.ids
+    }
+
+    pub fn get_offsets(&self) -> &Vec<f64> {
+        &self.offsets
+    }
+
+    pub fn get_entered_ids(&self) -> &Vec<usize> {
+        &self.entered_ids
+    }
+
+    pub fn get_left_uids(&self) -> &Vec<usize> {
+        &self.left_uids
+    }
+}
@@ -0,0 +1,379 @@
+// Snapshot interpolation for remote spheres on a networked client.
+// Snapshots are stored per sphere uid with the server time they were taken at,
+// and spheres are drawn `render_delay` seconds in the past, so there are usually
+// two snapshots around the render time to interpolate between. When a snapshot is
+// late, the last movement is extrapolated for at most `max_extrapolation` seconds.
+
+use std::collections::{HashMap, VecDeque};
+
+use wasm_bindgen::prelude::*;
+
+use crate::{
+    error::{check_finite, check_positive, ApiError},
+    utils::wrap_delta,
+};
+
+#[derive(Clone, Copy)]
+struct Sample {
+    time: f64,
+    x: f64,
+    y: f64,
+    r: f64,
+}
+
+struct Track {
+    samples: VecDeque<Sample>,
+    // time of the first snapshot the sphere was missing from
+    despawned_at: Option<f64>,
+}
+
+#[wasm_bindgen]
+pub struct Interpolator {
+    tracks: HashMap<usize, Track>,
+    render_delay: f64,
+    max_extrapolation: f64,
+    // world size for a toroidal world, 0 if positions do not wrap
+    wrap_size: f64,
+    snapshot_time: f64,
+    latest_snapshot_time: f64,
+    snapshot_uids: Vec<usize>,
+    removed_uids: Vec<usize>,
+    // [count, uid, x, y, r, uid, x, y, r, ...]
+    output: Vec<f64>,
+}
+
+#[wasm_bindgen]
+impl Interpolator {
+    pub fn new(render_delay: f64, max_extrapolation: f64) -> Result<Interpolator, ApiError> {
+        check_positive(&[render_delay, max_extrapolation])?;
+
+        return Ok(Interpolator {
+            tracks: HashMap::new(),
+            render_delay,
+            max_extrapolation,
+            wrap_size: 0.0,
+            snapshot_time: 0.0,
+            latest_snapshot_time: f64::MIN,
+            snapshot_uids: vec![],
+            removed_uids: vec![],
+            output: vec![],
+        });
+    }
+
+    pub fn set_render_delay(&mut self, render_delay: f64) -> Result<(), ApiError> {
+        check_positive(&[render_delay])?;
+
+        self.render_delay = render_delay;
+
+        return Ok(());
+    }
+
+    pub fn set_max_extrapolation(&mut self, max_extrapolation: f64) -> Result<(), ApiError> {
+        check_positive(&[max_extrapolation])?;
+
+        self.max_extrapolation = max_extrapolation;
+
+        return Ok(());
+    }
+
+    pub fn set_wrap_size(&mut self, wrap_size: f64) -> Result<(), ApiError> {
+        check_positive(&[wrap_size])?;
+
+        self.wrap_size = wrap_size;
+
+        return Ok(());
+    }
+
+    // a NaN time would break the ordering of the samples
+    pub fn begin_snapshot(&mut self, time: f64) -> Result<(), ApiError> {
+        check_finite(&[time])?;
+
+        self.snapshot_time = time;
+        self.snapshot_uids.clear();
+
+        return Ok(());
+    }
+
+    pub fn add(&mut self, uid: usize, x: f64, y: f64, r: f64) -> Result<(), ApiError> {
+        check_finite(&[x, y, r])?;
+
+        let sample = Sample {
+            time: self.snapshot_time,
+            x,
+            y,
+            r,
+        };
+
+        self.snapshot_uids.push(uid);
+
+        let track = self.tracks.entry(uid).or_insert_with(|| Track {
+            samples: VecDeque::with_capacity(8),
+            despawned_at: None,
+        });
+
+        // snapshots can arrive out of order, keep the samples sorted by time
+        let mut i = track.samples.len();
+
+        while i > 0 && track.samples[i - 1].time > sample.time {
+            i -= 1;
+        }
+
+        if i > 0 && track.samples[i - 1].time == sample.time {
+            track.samples[i - 1] = sample;
+        } else {
+            track.samples.insert(i, sample);
+        }
+
+        if let Some(despawned_at) = track.despawned_at {
+            if sample.time >= despawned_at {
+                track.despawned_at = None;
+            }
+        }
+
+        return Ok(());
+    }
+
+    // spheres missing from the newest snapshot are despawned once the render time reaches it
+    pub fn end_snapshot(&mut self) {
+        if self.snapshot_time < self.latest_snapshot_time {
+            return;
+        }
+
+        self.latest_snapshot_time = self.snapshot_time;
+        self.snapshot_uids.sort_unstable();
+
+        for (uid, track) in self.tracks.iter_mut() {
+            if track.despawned_at.is_none() && self.snapshot_uids.binary_search(uid).is_err() {
+                track.despawned_at = Some(self.snapshot_time);
+            }
+        }
+    }
+
+    // interpolate every sphere at now - render_delay, returns a pointer to
+    // [count, uid, x, y, r, uid, x, y, r, ...]
+    pub fn sample(&mut self, now: f64) -> Result<*const f64, ApiError> {
+        check_finite(&[now])?;
+
+        let time = now - self.render_delay;
+
+        self.output.clear();
+        self.output.push(0.0);
+        self.removed_uids.clear();
+
+        for (uid, track) in self.tracks.iter_mut() {
+            if let Some(despawned_at) = track.despawned_at {
+                if time >= despawned_at {
+                    self.removed_uids.push(*uid);
+                    continue;
+                }
+            }
+
+            // keep only one sample older than the render time
+            while track.samples.len() > 2 && track.samples[1].time <= time {
+                track.samples.pop_front();
+            }
+
+            if let Some((x, y, r)) =
+                Interpolator::sample_track(track, time, self.max_extrapolation, self.wrap_size)
+            {
+                self.output.push(*uid as f64);
+                self.output.push(x);
+                self.output.push(y);
+                self.output.push(r);
+            }
+        }
+
+        for uid in self.removed_uids.iter() {
+            self.tracks.remove(uid);
+        }
+
+        self.output[0] = ((self.output.len() - 1) / 4) as f64;
+
+        return Ok(self.output.as_ptr());
+    }
+
+    pub fn clear(&mut self) {
+        self.tracks.clear();
+        self.latest_snapshot_time = f64::MIN;
+    }
+}
+
+impl Interpolator {
+    fn sample_track(
+        track: &Track,
+        time: f64,
+        max_extrapolation: f64,
+        wrap_size: f64,
+    ) -> Option<(f64, f64, f64)> {
+        let first = track.samples.front()?;
+
+        // not spawned yet at the render time
+        if time < first.time {
+            return None;
+        }
+
+        let n = track.samples.len();
+
+        for i in 1..n {
+            let a = &track.samples[i - 1];
+            let b = &track.samples[i];
+
+            if time <= b.time {
+                let t = (time - a.time) / (b.time - a.time);
+
+                return Some(lerp_sample(a, b, t, wrap_size));
+            }
+        }
+
+        // the render time is past the newest snapshot, extrapolate a little
+        let last = &track.samples[n - 1];
+
+        if n < 2 {
+            return Some((last.x, last.y, last.r));
+        }
+
+        let prev = &track.samples[n - 2];
+        let elapsed = f64::min(time - last.time, max_extrapolation);
+        let t = 1.0 + elapsed / (last.time - prev.time);
+
+        let (x, y, _) = lerp_sample(prev, last, t, wrap_size);
+
+        // radius is not extrapolated, it could go negative
+        return Some((x, y, last.r));
+    }
+}
+
+fn lerp_sample(a: &Sample, b: &Sample, t: f64, wrap_size: f64) -> (f64, f64, f64) {
+    let (dx, dy) = if wrap_size > 0.0 {
+        (
+            wrap_delta(a.x, b.x, wrap_size),
+            wrap_delta(a.y, b.y, wrap_size),
+        )
+    } else {
+        (b.x - a.x, b.y - a.y)
+    };
+
+    let mut x = a.x + dx * t;
+    let mut y = a.y + dy * t;
+
+    if wrap_size > 0.0 {
+        x = f64::rem_euclid(x, wrap_size);
+        y = f64::rem_euclid(y, wrap_size);
+    }
+
+    (x, y, a.r + (b.r - a.r) * t)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Interpolator;
+    use crate::error::ApiError;
+
+    // one snapshot with one sphere
+    fn add_snapshot(interpolator: &mut Interpolator, time: f64, spheres: &[(usize, f64, f64)]) {
+        interpolator.begin_snapshot(time).unwrap();
+
+        for (uid, x, y) in spheres.iter() {
+            interpolator.add(*uid, *x, *y, 10.0).unwrap();
+        }
+
+        interpolator.end_snapshot();
+    }
+
+    // (x, y) of the sphere at the given time, none if it is not drawn
+    fn sample(interpolator: &mut Interpolator, now: f64, uid: usize) -> Option<(f64, f64)> {
+        interpolator.sample(now).unwrap();
+
+        let output = &interpolator.output;
+
+        for i in 0..(output[0] as usize) {
+            let item = &output[1 + i * 4..5 + i * 4];
+
+            if item[0] as usize == uid {
+                return Some((item[1], item[2]));
+            }
+        }
+
+        return None;
+    }
+
+    fn assert_near(a: (f64, f64), b: (f64, f64)) {
+        assert!(
+            (a.0 - b.0).abs() < 1e-9 && (a.1 - b.1).abs() < 1e-9,
+            "{:?} != {:?}",
+            a,
+            b
+        );
+    }
+
+    #[test]
+    fn interpolates_between_snapshots() {
+        let mut interpolator = Interpolator::new(0.1, 0.25).unwrap();
+        add_snapshot(&mut interpolator, 1.0, &[(1, 100.0, 200.0)]);
+        add_snapshot(&mut interpolator, 1.1, &[(1, 110.0, 180.0)]);
+
+        assert_near(sample(&mut interpolator, 1.15, 1).unwrap(), (105.0, 190.0));
+    }
+
+    #[test]
+    fn does_not_draw_before_the_first_snapshot() {
+        let mut interpolator = Interpolator::new(0.1, 0.25).unwrap();
+        add_snapshot(&mut interpolator, 1.0, &[(1, 100.0, 200.0)]);
+
+        assert_eq!(sample(&mut interpolator, 1.05, 1), None);
+        assert_near(sample(&mut interpolator, 1.1, 1).unwrap(), (100.0, 200.0));
+    }
+
+    #[test]
+    fn extrapolation_is_capped() {
+        let mut interpolator = Interpolator::new(0.0, 0.25).unwrap();
+        add_snapshot(&mut interpolator, 1.0, &[(1, 100.0, 0.0)]);
+        add_snapshot(&mut interpolator, 1.1, &[(1, 110.0, 0.0)]);
+
+        // 100 units per second past the last snapshot, for at most 0.25 seconds
+        assert_near(sample(&mut interpolator, 1.2, 1).unwrap(), (120.0, 0.0));
+        assert_near(sample(&mut interpolator, 1.35, 1).unwrap(), (135.0, 0.0));
+        assert_near(sample(&mut interpolator, 5.0, 1).unwrap(), (135.0, 0.0));
+    }
+
+    #[test]
+    fn despawns_at_the_render_time_of_the_first_snapshot_without_the_sphere() {
+        let mut interpolator = Interpolator::new(0.1, 0.25).unwrap();
+        add_snapshot(&mut interpolator, 1.0, &[(1, 100.0, 0.0), (2, 0.0, 0.0)]);
+        add_snapshot(&mut interpolator, 1.1, &[(2, 0.0, 0.0)]);
+
+        // still drawn until the render time reaches the snapshot it is missing from
+        assert!(sample(&mut interpolator, 1.15, 1).is_some());
+        assert_eq!(sample(&mut interpolator, 1.25, 1), None);
+        assert!(!interpolator.tracks.contains_key(&1));
+        assert!(sample(&mut interpolator, 1.25, 2).is_some());
+    }
+
+    #[test]
+    fn a_sphere_that_comes_back_is_not_despawned() {
+        let mut interpolator = Interpolator::new(0.1, 0.25).unwrap();
+        add_snapshot(&mut interpolator, 1.0, &[(1, 100.0, 0.0)]);
+        add_snapshot(&mut interpolator, 1.1, &[]);
+        add_snapshot(&mut interpolator, 1.2, &[(1, 120.0, 0.0)]);
+
+        assert_near(sample(&mut interpolator, 1.25, 1).unwrap(), (115.0, 0.0));
+    }
+
+    #[test]
+    fn wraps_across_the_toroidal_seam() {
+        let mut interpolator = Interpolator::new(0.0, 0.25).unwrap();
+        interpolator.set_wrap_size(1000.0).unwrap();
+        add_snapshot(&mut interpolator, 1.0, &[(1, 990.0, 500.0)]);
+        add_snapshot(&mut interpolator, 1.1, &[(1, 10.0, 500.0)]);
+
+        // the short way across the seam, not back through the middle of the world
+        assert_near(sample(&mut interpolator, 1.025, 1).unwrap(), (995.0, 500.0));
+        assert_near(sample(&mut interpolator, 1.075, 1).unwrap(), (5.0, 500.0));
+    }
+
+    #[test]
+    fn rejects_bad_delays() {
+        assert!(Interpolator::new(f64::NAN, 0.25).err() == Some(ApiError::NONFINITE));
+        assert!(Interpolator::new(0.1, -1.0).err() == Some(ApiError::NEGATIVE));
+    }
+}
@@ -0,0 +1,128 @@
+// Player ranking, rebuilt every update with one sort instead of inserting
+// players one by one. Bigger radius ranks higher, ties are broken by the
+// older sphere (smaller uid) so the order does not flicker between frames.
+
+#[derive(Clone, Copy)]
+struct Entry {
+    r: f64,
+    uid: usize,
+    id: usize,
+}
+
+pub struct Leaderboard {
+    entries: Vec<Entry>,
+    ids: Vec<usize>,
+}
+
+impl Leaderboard {
+    pub fn new(capacity: usize) -> Leaderboard {
+        Leaderboard {
+            entries: Vec::with_capacity(capacity),
+            ids: Vec::with_capacity(capacity),
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.entries.clear();
+        self.ids.clear();
+    }
+
+    pub fn push(&mut self, id: usize, uid: usize, r: f64) {
+        self.entries.push(Entry { r, uid, id });
+    }
+
+    // call after every player is pushed
+    pub fn sort(&mut self) {
+        self.entries
+            .sort_unstable_by(|a, b| f64::total_cmp(&b.r, &a.r).then(a.uid.cmp(&b.uid)));
+
+        self.ids.clear();
+
+        for entry in self.entries.iter() {
+            self.ids.push(entry.id);
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.ids.len()
+    }
+
+    // sphere ids, best first
+    pub fn get_ids(&self) -> &Vec<usize> {
+        &self.ids
+    }
+
+    pub fn get_top(&self, n: usize) -> &[usize] {
+        &self.ids[0..usize::min(n, self.ids.len())]
+    }
+
+    // up to `above` players ranked above the rank, the player at the rank, and up to `below`
+    // players ranked below it. rank starts from 1 like Sphere::rank
+    pub fn get_around(&self, rank: usize, above: usize, below: usize) -> &[usize] {
+        if rank == 0 || rank > self.ids.len() {
+            return &[];
+        }
+
+        let index = rank - 1;
+        let start = index.saturating_sub(above);
+        // below comes from JS and can be anything
+        let end = usize::min(
+            index.saturating_add(below).saturating_add(1),
+            self.ids.len(),
+        );
+
+        &self.ids[start..end]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Leaderboard;
+
+    // one player per radius, the id is the index and the uid 10 + id
+    fn new_leaderboard(radii: &[f64]) -> Leaderboard {
+        let mut leaderboard = Leaderboard::new(radii.len());
+
+        for (id, r) in radii.iter().enumerate() {
+            leaderboard.push(id, 10 + id, *r);
+        }
+
+        leaderboard.sort();
+
+        leaderboard
+    }
+
+    #[test]
+    fn ranks_by_radius_then_by_age() {
+        let mut leaderboard = Leaderboard::new(4);
+        leaderboard.push(0, 7, 10.0);
+        leaderboard.push(1, 3, 20.0);
+        leaderboard.push(2, 5, 10.0);
+        leaderboard.push(3, 1, 10.0);
+        leaderboard.sort();
+
+        // the same radius goes to the smaller uid first
+        assert_eq!(leaderboard.get_ids(), &vec![1, 3, 2, 0]);
+    }
+
+    #[test]
+    fn get_around_clamps_to_the_board() {
+        let leaderboard = new_leaderboard(&[50.0, 40.0, 30.0, 20.0, 10.0]);
+
+        assert_eq!(leaderboard.get_around(3, 1, 1), &[1, 2, 3]);
+        assert_eq!(leaderboard.get_around(1, 2, 1), &[0, 1]);
+        assert_eq!(leaderboard.get_around(5, 1, 3), &[3, 4]);
+        assert_eq!(
+            leaderboard.get_around(3, usize::MAX, usize::MAX),
+            &[0, 1, 2, 3, 4]
+        );
+    }
+
+    #[test]
+    fn get_around_is_empty_for_unknown_ranks() {
+        let leaderboard = new_leaderboard(&[50.0, 40.0]);
+
+        assert!(leaderboard.get_around(0, 1, 1).is_empty());
+        assert!(leaderboard.get_around(3, 1, 1).is_empty());
+    }
+}
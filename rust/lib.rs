@@ -1,15 +1,49 @@
 extern crate wee_alloc;
 
+pub mod achievements;
+pub mod camera;
+pub mod config;
 pub mod constants;
+pub mod error;
+pub mod events;
+pub mod highscore;
+pub mod input;
+pub mod interest;
+pub mod interpolation;
+pub mod leaderboard;
+pub mod minimap;
+pub mod obstacle;
+pub mod parallel;
 pub mod pool;
+pub mod prediction;
 pub mod quad_tree;
+pub mod sector;
+pub mod spectator;
 pub mod sphere;
+pub mod sphere_view;
 pub mod utils;
 pub mod world;
 
-use crate::constants::WORLD_SIZE;
+use crate::achievements::{default_definitions, AchievementDef, Achievements};
+use crate::camera::Camera;
+use crate::config::{ControlScheme, Topology, WorldConfig};
+use crate::constants::{
+    MAX_HIGHSCORE_ENTRIES, MINIMAP_RESOLUTION, SPECTATOR_FREE_VIEW_AREA, WORLD_RADIUS, WORLD_SIZE,
+};
+use error::{check_above_zero, check_finite, check_positive, ApiError};
+use events::WorldEvent;
+use highscore::{HighScoreEntry, HighScoreTable};
+use input::{Input, InputError};
+use interest::Viewer;
+use minimap::Minimap;
+use obstacle::Obstacle;
+use pool::{Handle, Pool};
+use spectator::{Spectator, SpectatorMode};
+use sphere::{Sphere, SphereType};
+use sphere_view::SphereView;
+use utils::{get_direction, get_view_size, now};
 use wasm_bindgen::prelude::*;
-use world::World;
+use world::{Command, World};
 
 // When the `wee_alloc` feature is enabled, use `wee_alloc` as the global
 // allocator.
@@ -17,23 +51,46 @@ use world::World;
 #[global_allocator]
 static ALLOC: wee_alloc::WeeAlloc = wee_alloc::WeeAlloc::INIT;
 
-#[wasm_bindgen]
-pub struct RegisterPlayerResponse(pub usize, pub usize);
-
 #[wasm_bindgen]
 pub struct Ubur {
     world: World,
-    visible_sphere_ids: Vec<usize>,
-    top_5_player_ids: Vec<usize>,
+    main_viewer: Viewer,
+    viewers: Pool<Viewer>,
+    // what a removed viewer sees
+    empty_ids: Vec<usize>,
+    empty_offsets: Vec<f64>,
+    leaderboard_ids: Vec<usize>,
+    highscores: HighScoreTable,
+    achievements: Achievements,
+    spectator: Spectator,
+    camera: Camera,
+    // spheres the camera frames, empty when it looks at a fixed spot
+    camera_targets: Vec<Handle>,
+    minimap: Minimap,
+    obstacle_data: Vec<f64>,
 }
 
 #[wasm_bindgen]
 impl Ubur {
     pub fn new() -> Ubur {
+        Ubur::with_config(WorldConfig::new())
+    }
+
+    pub fn with_config(config: WorldConfig) -> Ubur {
         Ubur {
-            world: World::new(),
-            visible_sphere_ids: vec![],
-            top_5_player_ids: Vec::with_capacity(6),
+            world: World::with_config(config),
+            main_viewer: Viewer::new(),
+            viewers: Pool::new(Viewer::new, 0),
+            empty_ids: vec![0],
+            empty_offsets: vec![],
+            leaderboard_ids: vec![],
+            highscores: HighScoreTable::new(MAX_HIGHSCORE_ENTRIES),
+            achievements: Achievements::new(default_definitions()),
+            spectator: Spectator::new(WORLD_SIZE * 0.5, WORLD_SIZE * 0.5),
+            camera: Camera::new(WORLD_SIZE * 0.5, WORLD_SIZE * 0.5, SPECTATOR_FREE_VIEW_AREA),
+            camera_targets: vec![],
+            minimap: Minimap::new(MINIMAP_RESOLUTION),
+            obstacle_data: vec![],
         }
     }
 
@@ -41,117 +98,653 @@ impl Ubur {
         WORLD_SIZE
     }
 
+    // only used by the circular arena, centered at (world_radius, world_radius)
+    pub fn world_radius() -> f64 {
+        WORLD_RADIUS
+    }
+
+    // world_size and world_radius are the starting values,
+    // these return the current ones when the world size is dynamic
+    pub fn get_world_size(&self) -> f64 {
+        self.world.size
+    }
+
+    pub fn get_world_radius(&self) -> f64 {
+        self.world.size * 0.5
+    }
+
+    pub fn get_topology(&self) -> Topology {
+        self.world.config.topology
+    }
+
     pub fn init(&mut self) {
         self.world.init();
     }
 
-    pub fn update(&mut self, dt: f64) {
-        self.world.update(dt)
+    // release the memory of unused sphere slots, applies on the next update
+    pub fn shrink_to_fit(&mut self) {
+        self.world.shrink_to_fit();
     }
 
-    pub fn register_player(&mut self, name: String) -> RegisterPlayerResponse {
-        let (id, uid) = self.world.spawn_player(name);
+    // every entry point checks its numbers, a NaN, infinite or negative dt throws
+    pub fn update(&mut self, dt: f64) -> Result<(), ApiError> {
+        check_positive(&[dt])?;
+
+        self.world.update(dt);
+
+        self.achievements.handle_events(&self.world.events, now());
 
-        return RegisterPlayerResponse(id, uid);
+        for event in self.world.events.iter() {
+            if let WorldEvent::Death {
+                is_fake: false,
+                name,
+                peak_score,
+                time_alive,
+                kills,
+                ..
+            } = event
+            {
+                self.highscores.add(HighScoreEntry {
+                    name: name.clone(),
+                    score: *peak_score,
+                    time_alive: *time_alive,
+                    kills: *kills,
+                    date: now(),
+                });
+            }
+        }
+
+        return Ok(());
     }
 
-    pub fn get_sphere_x(&self, id: usize) -> f64 {
-        self.world.spheres.get(id).x
+    // returns the handle of the player sphere, it stops resolving when the player dies.
+    // none if the world is full or obstacles leave no room to spawn
+    pub fn register_player(&mut self, name: String) -> Option<f64> {
+        let handle = self.world.spawn_player(name).ok()?;
+        let uid = self.world.spheres.at(handle.index).uid;
+
+        // achievements follow the latest registered player
+        self.achievements.set_player(Some(uid));
+
+        return Some(handle.to_f64());
     }
 
-    pub fn get_sphere_y(&self, id: usize) -> f64 {
-        self.world.spheres.get(id).y
+    // id of the sphere for the other getters, none if the sphere is gone.
+    // ids are only valid until the next update, handles can be kept
+    pub fn get_sphere_id(&self, handle: f64) -> Option<usize> {
+        let handle = Handle::from_f64(handle)?;
+
+        if !self.world.spheres.is_valid(handle) {
+            return None;
+        }
+
+        return Some(handle.index);
+    }
+
+    // the state of the sphere, undefined for an id that is not alive
+    pub fn get_sphere_view(&self, id: usize) -> Option<SphereView> {
+        let sphere = self.get_sphere(id)?;
+
+        return Some(SphereView::new(id, self.world.spheres.handle(id), sphere));
     }
 
-    pub fn get_sphere_r(&self, id: usize) -> f64 {
-        self.world.spheres.get(id).r
+    // views of the alive spheres among ids, in the same order, e.g. for the visible ids.
+    // ids that are not alive are left out, SphereView.id tells which is which
+    pub fn get_sphere_views(&self, ids: &[usize]) -> Vec<SphereView> {
+        let mut views = Vec::with_capacity(ids.len());
+
+        for id in ids.iter() {
+            if let Some(view) = self.get_sphere_view(*id) {
+                views.push(view);
+            }
+        }
+
+        return views;
     }
 
-    pub fn get_sphere_score(&self, id: usize) -> usize {
-        let r = self.world.spheres.get(id).r;
+    pub fn get_visible_sphere_ids(
+        &mut self,
+        aspect_ratio: f64,
+        x: f64,
+        y: f64,
+        view_area: f64,
+    ) -> Result<*const usize, ApiError> {
+        check_finite(&[x, y])?;
+        check_above_zero(&[aspect_ratio])?;
+        check_positive(&[view_area])?;
+
+        let (w, h) = get_view_size(aspect_ratio, view_area);
+
+        self.main_viewer.update(&self.world, x, y, w, h);
 
-        // return area, but instead of using std::f64::consts::PI i used 4.0
-        // so the max radius of 250 will have max score of 250000
-        f64::trunc(4.0 * r * r) as usize
+        return Ok(self.main_viewer.get_ids().as_ptr());
     }
 
-    pub fn get_sphere_color(&self, id: usize) -> u32 {
-        self.world.spheres.get(id).color
+    // (offset x, offset y) pairs to add to each sphere position from the last
+    // get_visible_sphere_ids call, they are only non zero in a toroidal world
+    pub fn get_visible_sphere_offsets(&self) -> *const f64 {
+        return self.main_viewer.get_offsets().as_ptr();
     }
 
-    pub fn get_sphere_name(&self, id: usize) -> Option<usize> {
-        let sphere = self.world.spheres.get(id);
+    // viewers track visibility separately, e.g. one per client on a server.
+    // returns the handle of the viewer
+    pub fn add_viewer(&mut self) -> Result<f64, ApiError> {
+        let (handle, viewer) = self.viewers.obtain().map_err(|_| ApiError::FULL)?;
+        viewer.reset();
 
-        return sphere.name;
+        return Ok(handle.to_f64());
     }
 
-    pub fn get_visible_sphere_ids(
+    pub fn remove_viewer(&mut self, viewer: f64) {
+        if let Some(handle) = Handle::from_f64(viewer) {
+            self.viewers.free(handle);
+        }
+    }
+
+    pub fn update_viewer(
         &mut self,
+        viewer: f64,
         aspect_ratio: f64,
         x: f64,
         y: f64,
         view_area: f64,
-    ) -> *const usize {
-        let h = f64::sqrt(view_area / aspect_ratio);
-        let w = aspect_ratio * h;
+    ) -> Result<(), ApiError> {
+        check_finite(&[x, y])?;
+        check_above_zero(&[aspect_ratio])?;
+        check_positive(&[view_area])?;
 
-        let left = x - w * 0.5;
-        let right = x + w * 0.5;
-        let top = y - h * 0.5;
-        let bottom = y + h * 0.5;
+        let (w, h) = get_view_size(aspect_ratio, view_area);
+        let viewer = Handle::from_f64(viewer).and_then(|handle| self.viewers.get_mut(handle));
 
-        self.visible_sphere_ids.clear();
-        self.visible_sphere_ids.push(0);
+        if let Some(viewer) = viewer {
+            viewer.update(&self.world, x, y, w, h);
+        }
 
-        for id in self.world.sphere_ids.iter() {
-            let sphere = self.world.spheres.get(*id);
+        return Ok(());
+    }
 
-            if sphere.x + sphere.r > left
-                && sphere.x - sphere.r < right
-                && sphere.y + sphere.r > top
-                && sphere.y - sphere.r < bottom
-            {
-                self.visible_sphere_ids.push(*id);
-            }
+    // the layout of these is the same as get_visible_sphere_ids and get_visible_sphere_offsets,
+    // a removed viewer sees nothing
+    pub fn get_viewer_visible_ids(&self, viewer: f64) -> *const usize {
+        match self.get_viewer(viewer) {
+            Some(viewer) => viewer.get_ids().as_ptr(),
+            None => self.empty_ids.as_ptr(),
         }
+    }
 
-        self.visible_sphere_ids[0] = self.visible_sphere_ids.len() - 1;
+    pub fn get_viewer_visible_offsets(&self, viewer: f64) -> *const f64 {
+        match self.get_viewer(viewer) {
+            Some(viewer) => viewer.get_offsets().as_ptr(),
+            None => self.empty_offsets.as_ptr(),
+        }
+    }
 
-        return self.visible_sphere_ids.as_ptr();
+    // [count, id, ...] of spheres that came into view in the last update_viewer call
+    pub fn get_viewer_entered_ids(&self, viewer: f64) -> *const usize {
+        match self.get_viewer(viewer) {
+            Some(viewer) => viewer.get_entered_ids().as_ptr(),
+            None => self.empty_ids.as_ptr(),
+        }
     }
 
-    pub fn get_sphere_view_area(&self, id: usize) -> f64 {
-        self.world.spheres.get(id).r * 2500.0 + 25000.0
+    // [count, uid, ...] of spheres that went out of view (or died) in the last update_viewer call
+    pub fn get_viewer_left_uids(&self, viewer: f64) -> *const usize {
+        match self.get_viewer(viewer) {
+            Some(viewer) => viewer.get_left_uids().as_ptr(),
+            None => self.empty_ids.as_ptr(),
+        }
     }
 
-    pub fn shoot(&mut self, id: usize, x: f64, y: f64) {
-        self.world.shoot(id, x, y);
+    pub fn get_sphere_view_area(&self, id: usize) -> Option<f64> {
+        Some(Camera::get_sphere_view_area(self.get_sphere(id)?.r))
     }
 
-    pub fn is_player_dead(&mut self, id: usize, uid: usize) -> bool {
-        let s = self.world.spheres.get(id);
+    // player is the handle from register_player, dead players are ignored.
+    // (x, y) is the direction, a zero one throws
+    pub fn shoot(&mut self, player: f64, x: f64, y: f64) -> Result<(), ApiError> {
+        check_finite(&[x, y])?;
+        get_direction(x, y).ok_or(ApiError::NODIRECTION)?;
 
-        return s.r <= 0.0 || s.uid != uid;
+        if let Some(handle) = Handle::from_f64(player) {
+            self.world.shoot(handle, x, y);
+        }
+
+        return Ok(());
+    }
+
+    pub fn move_to(&mut self, player: f64, x: f64, y: f64) -> Result<(), ApiError> {
+        check_finite(&[x, y])?;
+
+        if let Some(handle) = Handle::from_f64(player) {
+            self.world.move_to(handle, x, y);
+        }
+
+        return Ok(());
+    }
+
+    pub fn get_tick(&self) -> u32 {
+        self.world.tick
+    }
+
+    // sequenced shoot input for networked play, (x, y) is the direction.
+    // returns None if the input is accepted
+    pub fn submit_shoot(
+        &mut self,
+        player: f64,
+        uid: usize,
+        seq: u32,
+        tick: u32,
+        x: f64,
+        y: f64,
+    ) -> Option<InputError> {
+        let handle = match Handle::from_f64(player) {
+            Some(handle) => handle,
+            None => return Some(InputError::UNOWNED),
+        };
+        let (dirx, diry) = match get_direction(x, y) {
+            Some(direction) => direction,
+            None => return Some(InputError::INVALID),
+        };
+        let command = Command::Shoot(handle, dirx, diry);
+
+        self.world
+            .submit_input(Input {
+                uid,
+                seq,
+                tick,
+                command,
+            })
+            .err()
+    }
+
+    // sequenced move input for networked play, (x, y) is the target in world coordinates.
+    // returns None if the input is accepted
+    pub fn submit_move(
+        &mut self,
+        player: f64,
+        uid: usize,
+        seq: u32,
+        tick: u32,
+        x: f64,
+        y: f64,
+    ) -> Option<InputError> {
+        let handle = match Handle::from_f64(player) {
+            Some(handle) => handle,
+            None => return Some(InputError::UNOWNED),
+        };
+        let command = Command::Move(handle, x, y);
+
+        self.world
+            .submit_input(Input {
+                uid,
+                seq,
+                tick,
+                command,
+            })
+            .err()
+    }
+
+    // the last input sequence number applied for the player, so clients can drop acknowledged
+    // inputs
+    pub fn get_last_processed_seq(&self, uid: usize) -> Option<u32> {
+        self.world.inputs.get_last_processed_seq(uid)
+    }
+
+    pub fn get_control_scheme(&self) -> ControlScheme {
+        self.world.config.control
+    }
+
+    pub fn is_player_dead(&self, player: f64) -> bool {
+        return self.get_sphere_id(player).is_none();
     }
 
     pub fn get_top_5_player_ids(&mut self) -> *const usize {
-        self.top_5_player_ids.clear();
+        self.get_top_player_ids(5)
+    }
+
+    // [count, id, ...] of the best n players
+    pub fn get_top_player_ids(&mut self, n: usize) -> *const usize {
+        self.leaderboard_ids.clear();
+        self.leaderboard_ids.push(0);
+        self.leaderboard_ids
+            .extend_from_slice(self.world.leaderboard.get_top(n));
+        self.leaderboard_ids[0] = self.leaderboard_ids.len() - 1;
 
-        let len = usize::min(self.world.highscore_player_ids.len(), 5);
+        return self.leaderboard_ids.as_ptr();
+    }
+
+    // [count, id, ...] of up to `above` players ranked above the player, the player itself,
+    // and up to `below` players ranked below, best first. empty if the id is not a player
+    pub fn get_player_ids_around(&mut self, id: usize, above: usize, below: usize) -> *const usize {
+        let rank = match self.get_sphere(id) {
+            Some(sphere) if sphere.r#type == SphereType::PLAYER => sphere.rank,
+            _ => 0,
+        };
+
+        self.leaderboard_ids.clear();
+        self.leaderboard_ids.push(0);
+        self.leaderboard_ids
+            .extend_from_slice(self.world.leaderboard.get_around(rank, above, below));
+        self.leaderboard_ids[0] = self.leaderboard_ids.len() - 1;
+
+        return self.leaderboard_ids.as_ptr();
+    }
 
-        self.top_5_player_ids.push(len);
+    pub fn add_rect_obstacle(&mut self, x: f64, y: f64, w: f64, h: f64) -> Result<(), ApiError> {
+        self.world.add_obstacle(Obstacle::rect(x, y, w, h)?);
 
-        for i in 0..len {
-            self.top_5_player_ids
-                .push(self.world.highscore_player_ids[i]);
+        return Ok(());
+    }
+
+    pub fn add_circle_obstacle(&mut self, x: f64, y: f64, r: f64) -> Result<(), ApiError> {
+        self.world.add_obstacle(Obstacle::circle(x, y, r)?);
+
+        return Ok(());
+    }
+
+    // points are flattened as [x1, y1, x2, y2, ...]
+    pub fn add_polygon_obstacle(&mut self, points: Vec<f64>) -> Result<(), ApiError> {
+        self.world.add_obstacle(Obstacle::polygon(&points)?);
+
+        return Ok(());
+    }
+
+    pub fn clear_obstacles(&mut self) {
+        self.world.obstacles.clear();
+    }
+
+    // the first value is the obstacle count, followed by each obstacle packed by Obstacle::write
+    pub fn get_obstacles(&mut self) -> *const f64 {
+        self.obstacle_data.clear();
+        self.obstacle_data.push(self.world.obstacles.len() as f64);
+
+        for obstacle in self.world.obstacles.iter() {
+            obstacle.write(&mut self.obstacle_data);
         }
 
-        return self.top_5_player_ids.as_ptr();
+        return self.obstacle_data.as_ptr();
+    }
+
+    // the all time high scores as a text blob, e.g. for localStorage
+    pub fn export_highscores(&self) -> String {
+        self.highscores.serialize()
     }
 
-    pub fn get_sphere_rank(&mut self, id: usize) -> usize {
-        let sphere = self.world.spheres.get(id);
+    pub fn import_highscores(&mut self, blob: &str) {
+        self.highscores = HighScoreTable::deserialize(blob, MAX_HIGHSCORE_ENTRIES);
+    }
+
+    pub fn get_highscore_count(&self) -> usize {
+        self.highscores.len()
+    }
+
+    // index 0 is the best
+    pub fn get_highscore(&self, index: usize) -> Option<HighScoreEntry> {
+        self.highscores.get(index).cloned()
+    }
+
+    pub fn get_personal_best(&self, name: &str) -> Option<HighScoreEntry> {
+        self.highscores.get_personal_best(name).cloned()
+    }
+
+    // replaces the achievement with the same key
+    pub fn add_achievement(&mut self, def: AchievementDef) -> Result<(), ApiError> {
+        self.achievements.add(def)
+    }
+
+    pub fn clear_achievements(&mut self) {
+        self.achievements.clear();
+    }
+
+    pub fn get_achievement_count(&self) -> usize {
+        self.achievements.len()
+    }
+
+    pub fn get_achievement(&self, index: usize) -> Option<AchievementDef> {
+        self.achievements.get_def(index).cloned()
+    }
+
+    pub fn get_achievement_progress(&self, index: usize) -> f64 {
+        self.achievements.get_progress(index)
+    }
+
+    pub fn get_achievement_unlocked_at(&self, index: usize) -> Option<f64> {
+        self.achievements.get_unlocked_at(index)
+    }
+
+    // returns a pointer to [count, index, index, ...] of the achievements unlocked
+    // in the last update
+    pub fn get_unlocked_achievements(&self) -> *const usize {
+        return self.achievements.get_unlocked().as_ptr();
+    }
+
+    pub fn export_achievements(&self) -> String {
+        self.achievements.serialize()
+    }
+
+    pub fn import_achievements(&mut self, blob: &str) {
+        self.achievements.deserialize(blob);
+    }
+
+    // spectator camera, for after death and the title screen
+    pub fn set_spectator_mode(&mut self, mode: SpectatorMode) {
+        // the free camera starts where the camera is
+        if mode == SpectatorMode::FREE {
+            self.spectator
+                .set_free_view(self.camera.x, self.camera.y, self.camera.view_area);
+        }
+
+        self.spectator.set_mode(mode);
+    }
+
+    pub fn get_spectator_mode(&self) -> SpectatorMode {
+        self.spectator.mode
+    }
+
+    // where SpectatorMode::FREE looks
+    pub fn set_spectator_free_view(
+        &mut self,
+        x: f64,
+        y: f64,
+        view_area: f64,
+    ) -> Result<(), ApiError> {
+        check_finite(&[x, y])?;
+        check_positive(&[view_area])?;
+
+        self.spectator.set_free_view(x, y, view_area);
+
+        return Ok(());
+    }
+
+    // switches to SpectatorMode::CYCLE and follows the next player down the leaderboard
+    pub fn spectate_next(&mut self) {
+        self.spectator.cycle(&self.world, 1);
+    }
+
+    pub fn spectate_prev(&mut self) {
+        self.spectator.cycle(&self.world, -1);
+    }
+
+    // call after update, points the camera at what the spectator watches
+    pub fn update_spectator(&mut self, dt: f64) -> Result<(), ApiError> {
+        check_positive(&[dt])?;
+
+        self.spectator.update(&self.world, dt);
+
+        match self.spectator.get_target(&self.world) {
+            Some(handle) => {
+                self.camera_targets.clear();
+                self.camera_targets.push(handle);
+            }
+            None => {
+                let (x, y, view_area) = self.spectator.get_free_view();
+                self.camera_targets.clear();
+                self.camera.look_at(x, y, view_area);
+            }
+        }
+
+        return Ok(());
+    }
+
+    // id of the followed sphere, none in SpectatorMode::FREE
+    pub fn get_spectator_target(&self) -> Option<usize> {
+        self.spectator.get_target_id(&self.world)
+    }
+
+    // size of the screen in pixels, for the aspect ratio and the coordinate conversions
+    pub fn set_camera_screen(&mut self, width: f64, height: f64) -> Result<(), ApiError> {
+        check_positive(&[width, height])?;
+
+        self.camera.set_screen(width, height);
+
+        return Ok(());
+    }
+
+    pub fn set_camera_clamp(&mut self, clamp_to_world: bool) {
+        self.camera.clamp_to_world = clamp_to_world;
+    }
+
+    // sphere is a handle, like the one from register_player
+    pub fn camera_follow(&mut self, sphere: f64) {
+        self.camera_targets.clear();
+        self.camera_add_target(sphere);
+    }
+
+    // frame this sphere too, together with the followed ones
+    pub fn camera_add_target(&mut self, sphere: f64) {
+        if let Some(handle) = Handle::from_f64(sphere) {
+            if !self.camera_targets.contains(&handle) {
+                self.camera_targets.push(handle);
+            }
+        }
+    }
+
+    pub fn camera_look_at(&mut self, x: f64, y: f64, view_area: f64) -> Result<(), ApiError> {
+        check_finite(&[x, y])?;
+        check_positive(&[view_area])?;
+
+        self.camera_targets.clear();
+        self.camera.look_at(x, y, view_area);
+
+        return Ok(());
+    }
+
+    // jump to the target on the next update_camera, e.g. after spawning
+    pub fn camera_snap(&mut self) {
+        self.camera.snap();
+    }
+
+    // call once per frame after update, keeps the last target when all followed spheres are dead
+    pub fn update_camera(&mut self, dt: f64) -> Result<(), ApiError> {
+        check_positive(&[dt])?;
+
+        self.camera.frame(&self.world, &self.camera_targets);
+        self.camera.update(&self.world, dt);
+
+        return Ok(());
+    }
+
+    pub fn get_camera_x(&self) -> f64 {
+        self.camera.x
+    }
+
+    pub fn get_camera_y(&self) -> f64 {
+        self.camera.y
+    }
+
+    pub fn get_camera_view_area(&self) -> f64 {
+        self.camera.view_area
+    }
+
+    pub fn get_camera_width(&self) -> f64 {
+        self.camera.get_size().0
+    }
+
+    pub fn get_camera_height(&self) -> f64 {
+        self.camera.get_size().1
+    }
+
+    pub fn screen_to_world_x(&self, sx: f64, sy: f64) -> Result<f64, ApiError> {
+        check_finite(&[sx, sy])?;
+
+        return Ok(self.camera.screen_to_world(sx, sy).0);
+    }
+
+    pub fn screen_to_world_y(&self, sx: f64, sy: f64) -> Result<f64, ApiError> {
+        check_finite(&[sx, sy])?;
+
+        return Ok(self.camera.screen_to_world(sx, sy).1);
+    }
+
+    // the nearest copy of the position is used in a toroidal world
+    pub fn world_to_screen_x(&self, x: f64, y: f64) -> Result<f64, ApiError> {
+        check_finite(&[x, y])?;
+
+        return Ok(self.world_to_screen(x, y).0);
+    }
+
+    pub fn world_to_screen_y(&self, x: f64, y: f64) -> Result<f64, ApiError> {
+        check_finite(&[x, y])?;
+
+        return Ok(self.world_to_screen(x, y).1);
+    }
+
+    // like get_visible_sphere_ids with the camera view,
+    // the offsets are in get_visible_sphere_offsets
+    pub fn get_camera_visible_ids(&mut self) -> *const usize {
+        let (w, h) = self.camera.get_size();
+
+        self.main_viewer
+            .update(&self.world, self.camera.x, self.camera.y, w, h);
+
+        return self.main_viewer.get_ids().as_ptr();
+    }
+
+    // number of minimap cells along each side, at least one, at most MAX_MINIMAP_RESOLUTION
+    pub fn set_minimap_resolution(&mut self, resolution: usize) -> Result<(), ApiError> {
+        if resolution == 0 {
+            return Err(ApiError::ZERO);
+        }
+
+        self.minimap.set_resolution(resolution);
+
+        return Ok(());
+    }
+
+    // returns a pointer to the minimap, the layout is
+    // [resolution, type count, cell size,
+    //  density of each sphere type (indexed by SphereType) for every cell, row by row,
+    //  player count, id, x, y, r, ... of the best top_players players,
+    //  am count, x, y, r, ... of every anti matter sphere]
+    pub fn get_minimap(&mut self, top_players: usize) -> *const f64 {
+        self.minimap.update(&self.world, top_players);
+
+        return self.minimap.get_data().as_ptr();
+    }
+
+    // number of f64 values in the last get_minimap buffer
+    pub fn get_minimap_len(&self) -> usize {
+        self.minimap.get_data().len()
+    }
+}
+
+impl Ubur {
+    fn get_sphere(&self, id: usize) -> Option<&Sphere> {
+        if !self.world.spheres.is_alive(id) {
+            return None;
+        }
+
+        return Some(self.world.spheres.at(id));
+    }
+
+    fn get_viewer(&self, viewer: f64) -> Option<&Viewer> {
+        self.viewers.get(Handle::from_f64(viewer)?)
+    }
 
-        return sphere.rank;
+    fn world_to_screen(&self, x: f64, y: f64) -> (f64, f64) {
+        self.camera.world_to_screen(
+            self.camera.x + self.world.delta(self.camera.x, x),
+            self.camera.y + self.world.delta(self.camera.y, y),
+        )
     }
 }
@@ -0,0 +1,109 @@
+// Low resolution summary of the whole world for drawing a minimap.
+// The world is split into resolution * resolution cells, and every cell gets the density
+// of each sphere type (the part of the cell covered by spheres of that type, overlaps and
+// spheres crossing cell borders are counted in the cell of their center). The best players
+// and the anti matter spheres are listed with their positions, because they are the things
+// worth seeing at a glance. Everything is written to one buffer so JS reads it in one go.
+
+use std::f64::consts::PI;
+
+use crate::{
+    constants::{MAX_MINIMAP_RESOLUTION, SPHERE_TYPE_COUNT},
+    sphere::SphereType,
+    world::World,
+};
+
+pub struct Minimap {
+    pub resolution: usize,
+    // [resolution, type count, cell size,
+    //  density of type 0 to type count - 1 for every cell, row by row,
+    //  player count, id, x, y, r, id, x, y, r, ...,
+    //  am count, x, y, r, x, y, r, ...]
+    data: Vec<f64>,
+}
+
+impl Minimap {
+    pub fn new(resolution: usize) -> Minimap {
+        Minimap {
+            resolution: usize::clamp(resolution, 1, MAX_MINIMAP_RESOLUTION),
+            data: vec![],
+        }
+    }
+
+    pub fn set_resolution(&mut self, resolution: usize) {
+        self.resolution = usize::clamp(resolution, 1, MAX_MINIMAP_RESOLUTION);
+    }
+
+    pub fn get_data(&self) -> &Vec<f64> {
+        &self.data
+    }
+
+    // call after World::update, top_players is how many of the best players to list
+    pub fn update(&mut self, world: &World, top_players: usize) {
+        let n = self.resolution;
+        let cell_size = world.size / n as f64;
+        let cell_area = cell_size * cell_size;
+
+        self.data.clear();
+        self.data.push(n as f64);
+        self.data.push(SPHERE_TYPE_COUNT as f64);
+        self.data.push(cell_size);
+
+        let cells_start = self.data.len();
+        self.data
+            .resize(cells_start + n * n * SPHERE_TYPE_COUNT, 0.0);
+
+        // every alive sphere, also the ones in sleeping sectors
+        for id in world.spheres.get_alive().iter() {
+            let sphere = world.spheres.at(*id);
+
+            if sphere.r <= 0.0 {
+                continue;
+            }
+
+            let col = usize::min((f64::max(sphere.x, 0.0) / cell_size) as usize, n - 1);
+            let row = usize::min((f64::max(sphere.y, 0.0) / cell_size) as usize, n - 1);
+            let index = cells_start + (row * n + col) * SPHERE_TYPE_COUNT + sphere.r#type as usize;
+
+            self.data[index] += PI * sphere.r * sphere.r / cell_area;
+        }
+
+        // top players
+        let top = world.leaderboard.get_top(top_players);
+        let count_index = self.data.len();
+        self.data.push(0.0);
+
+        for id in top.iter() {
+            let sphere = world.spheres.at(*id);
+
+            if sphere.r <= 0.0 {
+                continue;
+            }
+
+            self.data.push(*id as f64);
+            self.data.push(sphere.x);
+            self.data.push(sphere.y);
+            self.data.push(sphere.r);
+        }
+
+        self.data[count_index] = ((self.data.len() - count_index - 1) / 4) as f64;
+
+        // anti matter
+        let count_index = self.data.len();
+        self.data.push(0.0);
+
+        for id in world.spheres.get_alive().iter() {
+            let sphere = world.spheres.at(*id);
+
+            if sphere.r#type != SphereType::AM || sphere.r <= 0.0 {
+                continue;
+            }
+
+            self.data.push(sphere.x);
+            self.data.push(sphere.y);
+            self.data.push(sphere.r);
+        }
+
+        self.data[count_index] = ((self.data.len() - count_index - 1) / 3) as f64;
+    }
+}
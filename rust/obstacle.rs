@@ -0,0 +1,227 @@
+// Static map geometry that spheres bounce off.
+// Rect uses top left as coordinate (same as IdQuad), Circle uses its center,
+// and Polygon is a list of points in order (clockwise or counter clockwise).
+
+use crate::error::{check_finite, check_positive, ApiError};
+
+pub enum Obstacle {
+    Rect { x: f64, y: f64, w: f64, h: f64 },
+    Circle { x: f64, y: f64, r: f64 },
+    Polygon { points: Vec<(f64, f64)> },
+}
+
+// used as the first value of each obstacle when exporting to the renderer
+pub static OBSTACLE_RECT: f64 = 0.0;
+pub static OBSTACLE_CIRCLE: f64 = 1.0;
+pub static OBSTACLE_POLYGON: f64 = 2.0;
+
+impl Obstacle {
+    // checked constructors for numbers coming from JS
+    pub fn rect(x: f64, y: f64, w: f64, h: f64) -> Result<Obstacle, ApiError> {
+        check_finite(&[x, y])?;
+        check_positive(&[w, h])?;
+
+        return Ok(Obstacle::Rect { x, y, w, h });
+    }
+
+    pub fn circle(x: f64, y: f64, r: f64) -> Result<Obstacle, ApiError> {
+        check_finite(&[x, y])?;
+        check_positive(&[r])?;
+
+        return Ok(Obstacle::Circle { x, y, r });
+    }
+
+    // points are flattened as [x1, y1, x2, y2, ...]
+    pub fn polygon(points: &[f64]) -> Result<Obstacle, ApiError> {
+        check_finite(points)?;
+
+        if !points.len().is_multiple_of(2) {
+            return Err(ApiError::BADPOLYGON);
+        }
+
+        let mut polygon = Vec::with_capacity(points.len() / 2);
+
+        for i in 0..(points.len() / 2) {
+            polygon.push((points[i * 2], points[i * 2 + 1]));
+        }
+
+        return Ok(Obstacle::Polygon { points: polygon });
+    }
+
+    // returns (normal x, normal y, penetration depth) if the circle overlaps the obstacle,
+    // the normal points out of the obstacle
+    pub fn get_contact(&self, x: f64, y: f64, r: f64) -> Option<(f64, f64, f64)> {
+        match self {
+            Obstacle::Rect { x: rx, y: ry, w, h } => {
+                let left = *rx;
+                let right = rx + w;
+                let top = *ry;
+                let bottom = ry + h;
+
+                let cx = f64::clamp(x, left, right);
+                let cy = f64::clamp(y, top, bottom);
+
+                if cx == x && cy == y {
+                    // center is inside the rect, push out through the nearest side
+                    let dl = x - left;
+                    let dr = right - x;
+                    let dt = y - top;
+                    let db = bottom - y;
+                    let min = f64::min(f64::min(dl, dr), f64::min(dt, db));
+
+                    if min == dl {
+                        return Some((-1.0, 0.0, dl + r));
+                    } else if min == dr {
+                        return Some((1.0, 0.0, dr + r));
+                    } else if min == dt {
+                        return Some((0.0, -1.0, dt + r));
+                    } else {
+                        return Some((0.0, 1.0, db + r));
+                    }
+                }
+
+                return contact_from_point(x, y, r, cx, cy);
+            }
+            Obstacle::Circle {
+                x: ox,
+                y: oy,
+                r: or,
+            } => {
+                let dx = x - ox;
+                let dy = y - oy;
+                let distance_sq = dx * dx + dy * dy;
+                let r_total = r + or;
+
+                if distance_sq >= r_total * r_total {
+                    return None;
+                }
+
+                let distance = f64::sqrt(distance_sq);
+
+                if distance == 0.0 {
+                    return Some((1.0, 0.0, r_total));
+                }
+
+                return Some((dx / distance, dy / distance, r_total - distance));
+            }
+            Obstacle::Polygon { points } => {
+                let n = points.len();
+
+                if n < 3 {
+                    return None;
+                }
+
+                let mut cx = 0.0;
+                let mut cy = 0.0;
+                let mut min_distance_sq = f64::MAX;
+
+                for i in 0..n {
+                    let (ax, ay) = points[i];
+                    let (bx, by) = points[(i + 1) % n];
+                    let (px, py) = closest_point_on_segment(x, y, ax, ay, bx, by);
+                    let distance_sq = (x - px) * (x - px) + (y - py) * (y - py);
+
+                    if distance_sq < min_distance_sq {
+                        min_distance_sq = distance_sq;
+                        cx = px;
+                        cy = py;
+                    }
+                }
+
+                if is_point_in_polygon(x, y, points) {
+                    let distance = f64::sqrt(min_distance_sq);
+
+                    if distance == 0.0 {
+                        return Some((1.0, 0.0, r));
+                    }
+
+                    return Some(((cx - x) / distance, (cy - y) / distance, distance + r));
+                }
+
+                return contact_from_point(x, y, r, cx, cy);
+            }
+        }
+    }
+
+    // pack the obstacle for the renderer:
+    // rect is [OBSTACLE_RECT, x, y, w, h], circle is [OBSTACLE_CIRCLE, x, y, r],
+    // polygon is [OBSTACLE_POLYGON, point count, x1, y1, x2, y2, ...]
+    pub fn write(&self, out: &mut Vec<f64>) {
+        match self {
+            Obstacle::Rect { x, y, w, h } => {
+                out.push(OBSTACLE_RECT);
+                out.push(*x);
+                out.push(*y);
+                out.push(*w);
+                out.push(*h);
+            }
+            Obstacle::Circle { x, y, r } => {
+                out.push(OBSTACLE_CIRCLE);
+                out.push(*x);
+                out.push(*y);
+                out.push(*r);
+            }
+            Obstacle::Polygon { points } => {
+                out.push(OBSTACLE_POLYGON);
+                out.push(points.len() as f64);
+
+                for (x, y) in points.iter() {
+                    out.push(*x);
+                    out.push(*y);
+                }
+            }
+        }
+    }
+}
+
+fn contact_from_point(x: f64, y: f64, r: f64, px: f64, py: f64) -> Option<(f64, f64, f64)> {
+    let dx = x - px;
+    let dy = y - py;
+    let distance_sq = dx * dx + dy * dy;
+
+    if distance_sq >= r * r {
+        return None;
+    }
+
+    let distance = f64::sqrt(distance_sq);
+
+    if distance == 0.0 {
+        return None;
+    }
+
+    return Some((dx / distance, dy / distance, r - distance));
+}
+
+fn closest_point_on_segment(x: f64, y: f64, ax: f64, ay: f64, bx: f64, by: f64) -> (f64, f64) {
+    let abx = bx - ax;
+    let aby = by - ay;
+    let len_sq = abx * abx + aby * aby;
+
+    if len_sq == 0.0 {
+        return (ax, ay);
+    }
+
+    let t = ((x - ax) * abx + (y - ay) * aby) / len_sq;
+    let t = f64::clamp(t, 0.0, 1.0);
+
+    return (ax + abx * t, ay + aby * t);
+}
+
+fn is_point_in_polygon(x: f64, y: f64, points: &[(f64, f64)]) -> bool {
+    let n = points.len();
+    let mut inside = false;
+    let mut j = n - 1;
+
+    for i in 0..n {
+        let (xi, yi) = points[i];
+        let (xj, yj) = points[j];
+
+        if (yi > y) != (yj > y) && x < (xj - xi) * (y - yi) / (yj - yi) + xi {
+            inside = !inside;
+        }
+
+        j = i;
+    }
+
+    return inside;
+}
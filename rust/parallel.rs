@@ -0,0 +1,164 @@
+// Work split over threads for the native server. With the `parallel` cargo feature the
+// chunks of work run on a pool of worker threads, spawned once and kept for the rest of
+// the process. Without it (and always in the wasm build, which has no threads) the
+// chunks run one after the other on the calling thread.
+// Callers keep the results of every chunk apart and merge them in chunk order,
+// so the outcome never depends on the number of threads.
+
+use crate::constants::PARALLEL_MIN_CHUNK_LEN;
+
+// threads the world uses by default
+pub fn get_thread_count() -> usize {
+    #[cfg(all(feature = "parallel", not(target_arch = "wasm32")))]
+    return std::thread::available_parallelism().map_or(1, |n| n.get());
+
+    #[cfg(not(all(feature = "parallel", not(target_arch = "wasm32"))))]
+    return 1;
+}
+
+// length of the chunks to split n items into for the given number of threads,
+// small chunks are not worth a thread
+pub fn get_chunk_len(n: usize, threads: usize) -> usize {
+    let chunk_len = n.div_ceil(usize::max(threads, 1));
+
+    return usize::max(chunk_len, PARALLEL_MIN_CHUNK_LEN);
+}
+
+// call f on every chunk, and return once all of them are done
+pub fn for_each<T, F>(chunks: Vec<T>, f: F)
+where
+    T: Send,
+    F: Fn(T) + Sync,
+{
+    #[cfg(all(feature = "parallel", not(target_arch = "wasm32")))]
+    if chunks.len() > 1 {
+        workers::for_each(chunks, &f);
+        return;
+    }
+
+    for chunk in chunks {
+        f(chunk);
+    }
+}
+
+#[cfg(all(feature = "parallel", not(target_arch = "wasm32")))]
+mod workers {
+    use std::{
+        any::Any,
+        panic::{self, AssertUnwindSafe},
+        sync::{
+            mpsc::{self, Receiver, Sender},
+            Arc, Condvar, Mutex, OnceLock,
+        },
+        thread,
+    };
+
+    type Job = Box<dyn FnOnce() + Send + 'static>;
+
+    struct Workers {
+        sender: Mutex<Sender<Job>>,
+        receiver: Arc<Mutex<Receiver<Job>>>,
+        // threads spawned so far, more are added when a call has more chunks
+        count: Mutex<usize>,
+    }
+
+    // jobs of one call still running, and the first panic of one of them
+    struct Latch {
+        state: Mutex<(usize, Option<Box<dyn Any + Send>>)>,
+        done: Condvar,
+    }
+
+    static WORKERS: OnceLock<Workers> = OnceLock::new();
+
+    fn get_workers(count: usize) -> &'static Workers {
+        let workers = WORKERS.get_or_init(|| {
+            let (sender, receiver) = mpsc::channel::<Job>();
+
+            Workers {
+                sender: Mutex::new(sender),
+                receiver: Arc::new(Mutex::new(receiver)),
+                count: Mutex::new(0),
+            }
+        });
+
+        let mut spawned = workers.count.lock().unwrap();
+
+        while *spawned < count {
+            let receiver = Arc::clone(&workers.receiver);
+
+            thread::spawn(move || loop {
+                let job = match receiver.lock().unwrap().recv() {
+                    Ok(job) => job,
+                    Err(_) => return,
+                };
+
+                job();
+            });
+
+            *spawned += 1;
+        }
+
+        return workers;
+    }
+
+    // the calling thread takes the first chunk, the workers the others
+    pub fn for_each<T, F>(chunks: Vec<T>, f: &F)
+    where
+        T: Send,
+        F: Fn(T) + Sync,
+    {
+        let mut chunks = chunks.into_iter();
+        let first = chunks.next();
+        let workers = get_workers(chunks.len());
+        let latch = Arc::new(Latch {
+            state: Mutex::new((chunks.len(), None)),
+            done: Condvar::new(),
+        });
+
+        {
+            let sender = workers.sender.lock().unwrap();
+
+            for chunk in chunks {
+                let latch = Arc::clone(&latch);
+                let job: Box<dyn FnOnce() + Send + '_> = Box::new(move || {
+                    let result = panic::catch_unwind(AssertUnwindSafe(|| f(chunk)));
+                    let mut state = latch.state.lock().unwrap();
+
+                    state.0 -= 1;
+
+                    if let Err(payload) = result {
+                        state.1.get_or_insert(payload);
+                    }
+
+                    latch.done.notify_all();
+                });
+
+                // SAFETY: the job borrows f and the chunks from this call, which waits
+                // below until every job has run, even when one of them panics
+                let job: Job = unsafe { std::mem::transmute(job) };
+
+                sender.send(job).unwrap();
+            }
+        }
+
+        let result = match first {
+            Some(chunk) => panic::catch_unwind(AssertUnwindSafe(|| f(chunk))),
+            None => Ok(()),
+        };
+
+        let mut state = latch.state.lock().unwrap();
+
+        while state.0 > 0 {
+            state = latch.done.wait(state).unwrap();
+        }
+
+        if let Err(payload) = result {
+            panic::resume_unwind(payload);
+        }
+
+        if let Some(payload) = state.1.take() {
+            drop(state);
+            panic::resume_unwind(payload);
+        }
+    }
+}
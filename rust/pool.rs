@@ -1,10 +1,68 @@
 use crate::utils::next_power_of_2;
 
+// generations wrap around before they stop fitting in the f64 of Handle::to_f64
+static MAX_GENERATION: u32 = 1 << 20;
+
+// Reference to a pooled object that stays valid only while the object is alive.
+// Every free bumps the generation of the slot, so a handle kept after the free
+// (or after the slot is reused) no longer resolves.
+#[derive(PartialEq, Eq, Hash, Clone, Copy, Debug)]
+pub struct Handle {
+    pub index: usize,
+    pub generation: u32,
+}
+
+impl Handle {
+    // never resolves, for commands that do not target a pooled sphere
+    pub const INVALID: Handle = Handle {
+        index: usize::MAX,
+        generation: 0,
+    };
+
+    // JS numbers can not hold a u64, so pack the handle into the integer range of an f64
+    pub fn to_f64(self) -> f64 {
+        (self.generation as f64) * 4294967296.0 + (self.index as f64)
+    }
+
+    pub fn from_f64(value: f64) -> Option<Handle> {
+        if !(value >= 0.0 && value.fract() == 0.0) {
+            return None;
+        }
+
+        let generation = f64::floor(value / 4294967296.0);
+
+        if generation >= MAX_GENERATION as f64 {
+            return None;
+        }
+
+        return Some(Handle {
+            index: (value - generation * 4294967296.0) as usize,
+            generation: generation as u32,
+        });
+    }
+}
+
+#[derive(PartialEq, Clone, Copy, Debug)]
+pub enum PoolError {
+    // the pool is at its max size and every object is alive
+    FULL,
+}
+
+// position in `alive` of a slot that is not alive
+static NOT_ALIVE: usize = usize::MAX;
+
 pub struct Pool<T> {
     pub objs: Vec<T>,
-    is_alive: Vec<bool>,
+    // indices of the alive objects in no particular order, so iterating them costs O(alive)
+    alive: Vec<usize>,
+    // position of each slot in `alive`, or NOT_ALIVE
+    alive_positions: Vec<usize>,
+    // kept for every slot ever created, even after shrink_to_fit, so old handles stay stale
+    generations: Vec<u32>,
     available_ids: Vec<usize>,
     current_size: usize,
+    // the pool never grows past this
+    max_size: Option<usize>,
     create_fn: fn() -> T,
 }
 
@@ -12,9 +70,12 @@ impl<T> Pool<T> {
     pub fn new(create_fn: fn() -> T, initial_size: usize) -> Pool<T> {
         let mut x = Pool {
             objs: Vec::with_capacity(initial_size),
-            is_alive: Vec::with_capacity(initial_size),
+            alive: Vec::with_capacity(initial_size),
+            alive_positions: Vec::with_capacity(initial_size),
+            generations: Vec::with_capacity(initial_size),
             available_ids: Vec::with_capacity(initial_size),
             current_size: 0,
+            max_size: None,
             create_fn,
         };
 
@@ -23,63 +84,316 @@ impl<T> Pool<T> {
         x
     }
 
+    // obtain fails with PoolError::FULL instead of growing past max_size.
+    // a pool that is already bigger keeps its size
+    pub fn set_max_size(&mut self, max_size: Option<usize>) {
+        self.max_size = max_size;
+    }
+
     pub fn expand(&mut self, new_size: usize) {
         for i in self.current_size..new_size {
             let new_obj = (self.create_fn)();
 
             self.objs.push(new_obj);
-            self.is_alive.push(false);
+            self.alive_positions.push(NOT_ALIVE);
+
+            if i == self.generations.len() {
+                self.generations.push(0);
+            }
+
             self.available_ids.push(i);
         }
 
+        self.current_size = usize::max(self.current_size, new_size);
+    }
+
+    // drop the free slots at the end of the pool and release the unused memory
+    pub fn shrink_to_fit(&mut self) {
+        let new_size = match self.alive.iter().max() {
+            Some(index) => index + 1,
+            None => 0,
+        };
+
+        self.objs.truncate(new_size);
+        self.alive_positions.truncate(new_size);
+        self.available_ids.retain(|id| *id < new_size);
         self.current_size = new_size;
+
+        self.objs.shrink_to_fit();
+        self.alive.shrink_to_fit();
+        self.alive_positions.shrink_to_fit();
+        self.available_ids.shrink_to_fit();
+    }
+
+    // checked access, None if the object was freed
+    pub fn get(&self, handle: Handle) -> Option<&T> {
+        if !self.is_valid(handle) {
+            return None;
+        }
+
+        return Some(&self.objs[handle.index]);
+    }
+
+    pub fn get_mut(&mut self, handle: Handle) -> Option<&mut T> {
+        if !self.is_valid(handle) {
+            return None;
+        }
+
+        return Some(&mut self.objs[handle.index]);
+    }
+
+    pub fn is_valid(&self, handle: Handle) -> bool {
+        return handle.index < self.current_size
+            && self.alive_positions[handle.index] != NOT_ALIVE
+            && self.generations[handle.index] == handle.generation;
+    }
+
+    pub fn is_alive(&self, index: usize) -> bool {
+        return index < self.current_size && self.alive_positions[index] != NOT_ALIVE;
+    }
+
+    // unchecked access by slot index, for ids that are known to be alive
+    // like the ones from get_alive_ids
+    pub fn at(&self, index: usize) -> &T {
+        return &self.objs[index];
+    }
+
+    pub fn at_mut(&mut self, index: usize) -> &mut T {
+        return &mut self.objs[index];
     }
 
-    pub fn get(&self, id: usize) -> &T {
-        return &self.objs[id];
+    // handle of the object currently in the slot
+    pub fn handle(&self, index: usize) -> Handle {
+        Handle {
+            index,
+            generation: self.generations[index],
+        }
     }
 
-    pub fn get_mut(&mut self, id: usize) -> &mut T {
-        return &mut self.objs[id];
+    // indices of the alive objects, in no particular order
+    pub fn get_alive(&self) -> &[usize] {
+        &self.alive
     }
 
-    pub fn get_alive_ids(&mut self, r: &mut Vec<usize>) {
+    // copy of get_alive, for iterating while objects are obtained or freed
+    pub fn get_alive_ids(&self, r: &mut Vec<usize>) {
         r.clear();
+        r.extend_from_slice(&self.alive);
+    }
 
-        for id in 0..self.objs.len() {
-            if self.is_alive[id] {
-                r.push(id);
-            }
+    pub fn is_full(&self) -> bool {
+        match self.max_size {
+            Some(max_size) => self.available_ids.len() == 0 && self.current_size >= max_size,
+            None => false,
         }
     }
 
-    pub fn obtain(&mut self) -> (usize, &mut T) {
+    pub fn obtain(&mut self) -> Result<(Handle, &mut T), PoolError> {
         if self.available_ids.len() == 0 {
-            self.expand(next_power_of_2(self.current_size + 1));
+            let mut new_size = next_power_of_2(self.current_size + 1);
+
+            if let Some(max_size) = self.max_size {
+                new_size = usize::min(new_size, max_size);
+            }
+
+            if new_size <= self.current_size {
+                return Err(PoolError::FULL);
+            }
+
+            self.expand(new_size);
         }
 
         let id = self.available_ids.pop().unwrap();
-        self.is_alive[id] = true;
+        self.alive_positions[id] = self.alive.len();
+        self.alive.push(id);
+        let handle = self.handle(id);
         let obj = &mut self.objs[id];
 
-        return (id, obj);
+        return Ok((handle, obj));
+    }
+
+    // returns false if the handle was already stale
+    pub fn free(&mut self, handle: Handle) -> bool {
+        if !self.is_valid(handle) {
+            return false;
+        }
+
+        self.free_slot(handle.index);
+
+        return true;
     }
 
-    pub fn free(&mut self, id: usize) {
-        self.is_alive[id] = false;
-        self.available_ids.push(id);
+    // reuse the slot for a new object right away, handles to the old one stop resolving
+    pub fn renew(&mut self, index: usize) -> Handle {
+        self.generations[index] = (self.generations[index] + 1) % MAX_GENERATION;
+
+        return self.handle(index);
     }
 
     pub fn clear(&mut self) {
-        self.available_ids.clear();
+        while let Some(index) = self.alive.last() {
+            self.free_slot(*index);
+        }
+    }
 
-        for i in 0..self.current_size {
-            self.is_alive[i] = false;
-            self.available_ids.push(i);
+    fn free_slot(&mut self, index: usize) {
+        // swap remove from the alive list, and fix the position of the moved index
+        let position = self.alive_positions[index];
+        self.alive.swap_remove(position);
+
+        if position < self.alive.len() {
+            self.alive_positions[self.alive[position]] = position;
         }
+
+        self.alive_positions[index] = NOT_ALIVE;
+        self.generations[index] = (self.generations[index] + 1) % MAX_GENERATION;
+        self.available_ids.push(index);
     }
 
     pub fn len(&self) -> usize {
-        self.current_size - self.available_ids.len()
+        self.alive.len()
+    }
+
+    // number of slots, alive or not
+    pub fn capacity(&self) -> usize {
+        self.current_size
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Handle, Pool, PoolError};
+
+    fn new_pool(initial_size: usize) -> Pool<usize> {
+        Pool::new(|| 0, initial_size)
+    }
+
+    fn sorted_alive(pool: &Pool<usize>) -> Vec<usize> {
+        let mut alive = pool.get_alive().to_vec();
+        alive.sort();
+
+        alive
+    }
+
+    #[test]
+    fn stale_handle_fails_after_free() {
+        let mut pool = new_pool(4);
+        let (handle, obj) = pool.obtain().unwrap();
+        *obj = 7;
+
+        assert_eq!(pool.get(handle), Some(&7));
+        assert!(pool.free(handle));
+
+        assert!(!pool.is_valid(handle));
+        assert_eq!(pool.get(handle), None);
+        assert!(!pool.free(handle));
+
+        // the slot is reused, the old handle still does not resolve
+        let (new_handle, _) = pool.obtain().unwrap();
+
+        assert_eq!(new_handle.index, handle.index);
+        assert!(pool.get(handle).is_none());
+        assert!(pool.get(new_handle).is_some());
+    }
+
+    #[test]
+    fn stale_handle_fails_after_renew() {
+        let mut pool = new_pool(4);
+        let (handle, _) = pool.obtain().unwrap();
+        let new_handle = pool.renew(handle.index);
+
+        assert_eq!(new_handle.index, handle.index);
+        assert!(pool.get(handle).is_none());
+        assert!(pool.get(new_handle).is_some());
+        assert_eq!(pool.len(), 1);
+    }
+
+    #[test]
+    fn alive_list_is_kept_after_swap_remove() {
+        let mut pool = new_pool(8);
+        let handles: Vec<Handle> = (0..5).map(|_| pool.obtain().unwrap().0).collect();
+
+        // free from the middle, the front and the back of the alive list
+        pool.free(handles[2]);
+        pool.free(handles[0]);
+        pool.free(handles[4]);
+
+        let mut expected = vec![handles[1].index, handles[3].index];
+        expected.sort();
+
+        assert_eq!(sorted_alive(&pool), expected);
+        assert_eq!(pool.len(), 2);
+
+        for handle in handles.iter() {
+            assert_eq!(
+                pool.is_alive(handle.index),
+                expected.contains(&handle.index)
+            );
+        }
+
+        // the moved entries can still be freed through their new position
+        pool.free(handles[3]);
+        pool.free(handles[1]);
+
+        assert!(pool.get_alive().is_empty());
+        assert_eq!(pool.len(), 0);
+    }
+
+    #[test]
+    fn shrink_to_fit_keeps_live_slots() {
+        let mut pool = new_pool(8);
+        let handles: Vec<Handle> = (0..6).map(|_| pool.obtain().unwrap().0).collect();
+
+        for (i, handle) in handles.iter().enumerate() {
+            *pool.get_mut(*handle).unwrap() = i;
+        }
+
+        let last = handles.iter().max_by_key(|handle| handle.index).unwrap();
+
+        for handle in handles.iter() {
+            if handle.index != last.index && handle.index % 2 == 0 {
+                pool.free(*handle);
+            }
+        }
+
+        let alive = sorted_alive(&pool);
+        pool.shrink_to_fit();
+
+        assert_eq!(pool.capacity(), last.index + 1);
+        assert_eq!(sorted_alive(&pool), alive);
+
+        for (i, handle) in handles.iter().enumerate() {
+            if alive.contains(&handle.index) {
+                assert_eq!(pool.get(*handle), Some(&i));
+            } else {
+                assert!(pool.get(*handle).is_none());
+            }
+        }
+
+        // slots above the new size are not handed out twice when the pool grows again
+        let mut obtained = alive.clone();
+
+        while pool.len() < 8 {
+            obtained.push(pool.obtain().unwrap().0.index);
+        }
+
+        obtained.sort();
+        obtained.dedup();
+
+        assert_eq!(obtained.len(), 8);
+    }
+
+    #[test]
+    fn obtain_fails_at_max_size() {
+        let mut pool = new_pool(2);
+        pool.set_max_size(Some(5));
+
+        for _ in 0..5 {
+            assert!(pool.obtain().is_ok());
+        }
+
+        assert!(pool.is_full());
+        assert_eq!(pool.obtain().err(), Some(PoolError::FULL));
+        assert_eq!(pool.capacity(), 5);
     }
 }
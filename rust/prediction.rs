@@ -0,0 +1,397 @@
+// Client side prediction of the local player sphere.
+// The client applies its own inputs right away with the same code the world uses,
+// keeps them until a snapshot from the server covers their tick, then replays the rest
+// on top of the snapshot. The difference between the old and the new prediction is
+// kept as an offset that fades out, so corrections are smooth.
+// The obstacles are static, give the predictor the same ones as the world so the bounces
+// off them are predicted too. Collisions with other spheres are not predicted.
+
+use std::collections::VecDeque;
+
+use wasm_bindgen::prelude::*;
+
+use crate::{
+    config::{Topology, WorldConfig},
+    constants::{PREDICTION_CORRECTION_RATE, PREDICTION_SNAP_DISTANCE},
+    error::{check_finite, check_positive, ApiError},
+    obstacle::Obstacle,
+    pool::Handle,
+    sphere::{Sphere, SphereType},
+    utils::{get_direction, wrap_delta},
+    world::{Command, World},
+};
+
+#[derive(Clone, Copy)]
+struct PendingTick {
+    tick: u32,
+    command: Option<Command>,
+    dt: f64,
+    // move target before the tick ran, so a replay starts from the one the server had
+    move_target: Option<(f64, f64)>,
+}
+
+#[wasm_bindgen]
+pub struct Predictor {
+    config: WorldConfig,
+    world_size: f64,
+    obstacles: Vec<Obstacle>,
+    sphere: Sphere,
+    pending: VecDeque<PendingTick>,
+    error_x: f64,
+    error_y: f64,
+    error_r: f64,
+}
+
+#[wasm_bindgen]
+impl Predictor {
+    pub fn new(config: WorldConfig, world_size: f64) -> Predictor {
+        let mut sphere = Sphere::zero();
+        sphere.r#type = SphereType::PLAYER;
+
+        Predictor {
+            config,
+            world_size,
+            obstacles: vec![],
+            sphere,
+            pending: VecDeque::with_capacity(128),
+            error_x: 0.0,
+            error_y: 0.0,
+            error_r: 0.0,
+        }
+    }
+
+    pub fn set_world_size(&mut self, world_size: f64) -> Result<(), ApiError> {
+        check_positive(&[world_size])?;
+
+        self.world_size = world_size;
+
+        return Ok(());
+    }
+
+    pub fn add_rect_obstacle(&mut self, x: f64, y: f64, w: f64, h: f64) -> Result<(), ApiError> {
+        self.obstacles.push(Obstacle::rect(x, y, w, h)?);
+
+        return Ok(());
+    }
+
+    pub fn add_circle_obstacle(&mut self, x: f64, y: f64, r: f64) -> Result<(), ApiError> {
+        self.obstacles.push(Obstacle::circle(x, y, r)?);
+
+        return Ok(());
+    }
+
+    // points are flattened as [x1, y1, x2, y2, ...]
+    pub fn add_polygon_obstacle(&mut self, points: Vec<f64>) -> Result<(), ApiError> {
+        self.obstacles.push(Obstacle::polygon(&points)?);
+
+        return Ok(());
+    }
+
+    pub fn clear_obstacles(&mut self) {
+        self.obstacles.clear();
+    }
+
+    // start predicting from the given state, dropping every pending input
+    pub fn reset(
+        &mut self,
+        x: f64,
+        y: f64,
+        vx: f64,
+        vy: f64,
+        r: f64,
+        shoot_delay: f64,
+    ) -> Result<(), ApiError> {
+        check_finite(&[x, y, vx, vy, r, shoot_delay])?;
+
+        self.set_state(x, y, vx, vy, r, shoot_delay);
+        self.pending.clear();
+        self.error_x = 0.0;
+        self.error_y = 0.0;
+        self.error_r = 0.0;
+
+        return Ok(());
+    }
+
+    // advance one client tick without input
+    pub fn predict(&mut self, tick: u32, dt: f64) -> Result<(), ApiError> {
+        check_positive(&[dt])?;
+
+        self.push_tick(tick, None, dt);
+
+        return Ok(());
+    }
+
+    // advance one client tick with a shot, (x, y) is the direction
+    pub fn predict_shoot(&mut self, tick: u32, dt: f64, x: f64, y: f64) -> Result<(), ApiError> {
+        check_positive(&[dt])?;
+        check_finite(&[x, y])?;
+
+        let (dirx, diry) = get_direction(x, y).ok_or(ApiError::NODIRECTION)?;
+
+        // the sphere id does not matter here, the predictor only has one sphere
+        self.push_tick(tick, Some(Command::Shoot(Handle::INVALID, dirx, diry)), dt);
+
+        return Ok(());
+    }
+
+    // advance one client tick with a move target in world coordinates
+    pub fn predict_move(&mut self, tick: u32, dt: f64, x: f64, y: f64) -> Result<(), ApiError> {
+        check_positive(&[dt])?;
+        check_finite(&[x, y])?;
+
+        self.push_tick(tick, Some(Command::Move(Handle::INVALID, x, y)), dt);
+
+        return Ok(());
+    }
+
+    // apply an authoritative state taken before the world processed `tick`.
+    // inputs for older ticks are acknowledged, the rest are replayed on top
+    pub fn reconcile(
+        &mut self,
+        tick: u32,
+        x: f64,
+        y: f64,
+        vx: f64,
+        vy: f64,
+        r: f64,
+        shoot_delay: f64,
+    ) -> Result<(), ApiError> {
+        check_finite(&[x, y, vx, vy, r, shoot_delay])?;
+
+        while let Some(pending) = self.pending.front() {
+            if pending.tick >= tick {
+                break;
+            }
+
+            self.pending.pop_front();
+        }
+
+        let (old_x, old_y, old_r) = self.get_render_state();
+
+        self.set_state(x, y, vx, vy, r, shoot_delay);
+
+        // without pending ticks every move is acknowledged and the newest target is right
+        if let Some(pending) = self.pending.front() {
+            self.sphere.move_target = pending.move_target;
+        }
+
+        for i in 0..self.pending.len() {
+            let pending = self.pending[i];
+
+            self.step(pending.command, pending.dt);
+        }
+
+        self.error_x = self.delta(self.sphere.x, old_x);
+        self.error_y = self.delta(self.sphere.y, old_y);
+        self.error_r = old_r - self.sphere.r;
+
+        // too far off to be worth smoothing
+        if self.error_x * self.error_x + self.error_y * self.error_y
+            > PREDICTION_SNAP_DISTANCE * PREDICTION_SNAP_DISTANCE
+        {
+            self.error_x = 0.0;
+            self.error_y = 0.0;
+            self.error_r = 0.0;
+        }
+
+        return Ok(());
+    }
+
+    pub fn get_pending_count(&self) -> usize {
+        self.pending.len()
+    }
+
+    // positions to draw, including the fading correction offset
+    pub fn get_x(&self) -> f64 {
+        self.sphere.x + self.error_x
+    }
+
+    pub fn get_y(&self) -> f64 {
+        self.sphere.y + self.error_y
+    }
+
+    pub fn get_r(&self) -> f64 {
+        self.sphere.r + self.error_r
+    }
+}
+
+impl Predictor {
+    fn set_state(&mut self, x: f64, y: f64, vx: f64, vy: f64, r: f64, shoot_delay: f64) {
+        self.sphere.x = x;
+        self.sphere.y = y;
+        self.sphere.vx = vx;
+        self.sphere.vy = vy;
+        self.sphere.r = r;
+        self.sphere.shoot_delay = shoot_delay;
+    }
+
+    fn get_render_state(&self) -> (f64, f64, f64) {
+        (self.get_x(), self.get_y(), self.get_r())
+    }
+
+    fn push_tick(&mut self, tick: u32, command: Option<Command>, dt: f64) {
+        self.pending.push_back(PendingTick {
+            tick,
+            command,
+            dt,
+            move_target: self.sphere.move_target,
+        });
+        self.step(command, dt);
+
+        let decay = f64::exp(-PREDICTION_CORRECTION_RATE * dt);
+
+        self.error_x *= decay;
+        self.error_y *= decay;
+        self.error_r *= decay;
+    }
+
+    // the same steps World::update does for one sphere, minus the collisions with spheres
+    fn step(&mut self, command: Option<Command>, dt: f64) {
+        match command {
+            Some(Command::Shoot(_, dirx, diry)) => {
+                if self.sphere.shoot_delay <= 0.0 {
+                    self.sphere.reset_shoot_delay();
+
+                    let (force, _, _) = World::get_shoot_force(&self.config, &self.sphere);
+                    self.sphere.shoot(dirx, diry, force, self.config.physics);
+                }
+            }
+            Some(Command::Move(_, x, y)) => {
+                self.sphere.move_target = Some((x, y));
+            }
+            None => {}
+        }
+
+        World::move_sphere(&self.config, self.world_size, &mut self.sphere, dt);
+        World::bounce_off_obstacles(&self.obstacles, &mut self.sphere);
+    }
+
+    fn delta(&self, a: f64, b: f64) -> f64 {
+        match self.config.topology {
+            Topology::TOROIDAL => wrap_delta(a, b, self.world_size),
+            Topology::BOUNDED | Topology::CIRCULAR => b - a,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Predictor;
+    use crate::config::{ControlScheme, WorldConfig};
+
+    fn new_predictor() -> Predictor {
+        let mut config = WorldConfig::new();
+        config.control = ControlScheme::MOVE;
+
+        let mut predictor = Predictor::new(config, 1000.0);
+        predictor.reset(500.0, 500.0, 0.0, 0.0, 10.0, 0.0).unwrap();
+
+        predictor
+    }
+
+    fn get_state(predictor: &Predictor) -> (f64, f64, f64, f64, f64, f64) {
+        let sphere = &predictor.sphere;
+
+        (
+            sphere.x,
+            sphere.y,
+            sphere.vx,
+            sphere.vy,
+            sphere.r,
+            sphere.shoot_delay,
+        )
+    }
+
+    // ticks 0 to 19 with two move targets and a shot in between, returns the state before
+    // every tick and after the last one
+    fn run(predictor: &mut Predictor) -> Vec<(f64, f64, f64, f64, f64, f64)> {
+        let dt = 1.0 / 60.0;
+        let mut states = vec![];
+
+        for tick in 0..20 {
+            states.push(get_state(predictor));
+
+            match tick {
+                2 => predictor.predict_move(tick, dt, 700.0, 400.0).unwrap(),
+                9 => predictor.predict_shoot(tick, dt, 1.0, 0.0).unwrap(),
+                12 => predictor.predict_move(tick, dt, 300.0, 600.0).unwrap(),
+                _ => predictor.predict(tick, dt).unwrap(),
+            }
+        }
+
+        states.push(get_state(predictor));
+
+        states
+    }
+
+    #[test]
+    fn reconcile_drops_the_acknowledged_ticks() {
+        let mut predictor = new_predictor();
+        let states = run(&mut predictor);
+        let (x, y, vx, vy, r, shoot_delay) = states[15];
+
+        predictor
+            .reconcile(15, x, y, vx, vy, r, shoot_delay)
+            .unwrap();
+
+        assert_eq!(predictor.get_pending_count(), 5);
+    }
+
+    #[test]
+    fn reconcile_with_the_predicted_state_changes_nothing() {
+        // before, between and after the two move targets
+        for tick in [1, 5, 10, 15] {
+            let mut predictor = new_predictor();
+            let states = run(&mut predictor);
+            let (x, y, vx, vy, r, shoot_delay) = states[tick as usize];
+
+            predictor
+                .reconcile(tick, x, y, vx, vy, r, shoot_delay)
+                .unwrap();
+
+            assert_eq!(get_state(&predictor), states[20]);
+            assert_eq!(predictor.get_x(), states[20].0);
+            assert_eq!(predictor.get_y(), states[20].1);
+        }
+    }
+
+    #[test]
+    fn reconcile_smooths_a_correction() {
+        let mut predictor = new_predictor();
+        let states = run(&mut predictor);
+        let (x, y, vx, vy, r, shoot_delay) = states[15];
+        let (old_x, old_y) = (predictor.get_x(), predictor.get_y());
+
+        predictor
+            .reconcile(15, x + 5.0, y, vx, vy, r, shoot_delay)
+            .unwrap();
+
+        // the sphere moved, but it is drawn where it was
+        assert!(predictor.sphere.x > states[20].0 + 4.0);
+        assert!((predictor.get_x() - old_x).abs() < 1e-9);
+        assert!((predictor.get_y() - old_y).abs() < 1e-9);
+
+        // and the offset fades out
+        for tick in 20..200 {
+            predictor.predict(tick, 1.0 / 60.0).unwrap();
+        }
+
+        assert!((predictor.get_x() - predictor.sphere.x).abs() < 0.01);
+    }
+
+    #[test]
+    fn predict_bounces_off_obstacles() {
+        let mut predictor = new_predictor();
+        predictor.add_circle_obstacle(530.0, 500.0, 15.0).unwrap();
+        predictor
+            .reset(500.0, 500.0, 100.0, 0.0, 10.0, 0.0)
+            .unwrap();
+
+        for tick in 0..10 {
+            predictor.predict(tick, 1.0 / 60.0).unwrap();
+        }
+
+        assert!(predictor.sphere.vx < 0.0);
+        assert!(predictor.sphere.x <= 530.0 - 15.0 - 10.0 + 1e-9);
+    }
+}
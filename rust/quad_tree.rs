@@ -1,8 +1,12 @@
 // A custom, incorrect Quad Tree implementation that only store usize id
 // But it works
 
+// width below which a node is not split anymore
+const MIN_NODE_SIZE: f64 = 4.0;
+
 pub struct IdQuad {
-    data: Vec<usize>,
+    // ids with their (x, y, w, h), to move them down when the node is split
+    data: Vec<(usize, f64, f64, f64, f64)>,
     children: Vec<IdQuad>,
     x: f64,
     y: f64,
@@ -34,15 +38,33 @@ impl IdQuad {
         IdQuad::_clear(self);
     }
 
+    // clear, and move the root node to new bounds. the children are only dropped
+    // if the bounds changed, they are rebuilt by the next inserts
+    pub fn reset(&mut self, x: f64, y: f64, w: f64, h: f64) {
+        if x == self.x && y == self.y && w == self.w && h == self.h {
+            self.clear();
+
+            return;
+        }
+
+        self.x = x;
+        self.y = y;
+        self.w = w;
+        self.h = h;
+        self.data.clear();
+        self.children.clear();
+    }
+
     fn _insert(node: &mut IdQuad, id: usize, x: f64, y: f64, w: f64, h: f64) {
         // if not intersecting, return
         if !_intersects(node, x, y, w, h) {
             return;
         }
 
-        // if no children and data size is smaller than the limit (4), insert data to the node
-        if node.children.len() == 0 && node.data.len() < 4 {
-            node.data.push(id);
+        // if no children and data size is smaller than the limit (4), insert data to the node.
+        // the smallest nodes are never split, more than 4 ids can overlap any region
+        if node.children.len() == 0 && (node.data.len() < 4 || node.w <= MIN_NODE_SIZE) {
+            node.data.push((id, x, y, w, h));
 
             return;
         }
@@ -61,6 +83,13 @@ impl IdQuad {
             node.children.push(tr);
             node.children.push(bl);
             node.children.push(br);
+
+            // only the leaves hold ids, so a query does not get every id of the nodes above
+            for (id, x, y, w, h) in node.data.drain(..) {
+                for i in 0..4 {
+                    IdQuad::_insert(&mut node.children[i], id, x, y, w, h);
+                }
+            }
         }
 
         for i in 0..4 {
@@ -80,7 +109,7 @@ impl IdQuad {
 
         // insert the data
         for i in 0..node.data.len() {
-            hs.push(node.data[i]);
+            hs.push(node.data[i].0);
         }
 
         // get data for all children
@@ -94,6 +123,11 @@ impl IdQuad {
 
         IdQuad::_get_data_in_region(self, x, y, w, h, hs);
     }
+
+    // same as get_data_in_region, but adds to what is already in hs
+    pub fn add_data_in_region(&self, x: f64, y: f64, w: f64, h: f64, hs: &mut Vec<usize>) {
+        IdQuad::_get_data_in_region(self, x, y, w, h, hs);
+    }
 }
 
 fn _intersects(node: &IdQuad, x: f64, y: f64, w: f64, h: f64) -> bool {
@@ -0,0 +1,464 @@
+// The world is split into square sectors of at most SECTOR_SIZE. Every sector has its own
+// spawn budget (its part of MAX_SPHERE_COUNT by area), so the sphere count grows with the
+// world area instead of stopping at one global limit, and a crowded sector does not starve
+// the others.
+// With WorldConfig::sleep, only the sectors around real players are awake: the spheres in
+// the other sectors keep their state but do not move or look for collisions. The sleeping
+// sectors next to an awake one are indexed, they stay in the quad tree so awake spheres
+// still run into them. The rest are not touched at all during an update, so the update
+// time follows the number of players rather than the size of the world.
+// Every sector keeps the list of its spheres. Only indexed sectors can change (the spheres
+// of an awake sector move at most into the indexed ring around it), so only their lists
+// are rebuilt, from the spheres that took part in the last update and the new ones.
+// Every sector also has its own quad tree, rebuilt only while it is indexed. Small trees
+// stay shallow however big the world is, and the sleeping ones are not even cleared.
+// A tree covers its sector plus MAX_SPHERE_R around it, so a sphere only goes in the tree
+// of the sector its center is in, and still is found by any region it overlaps. The bounds
+// of the spheres in every tree are kept too, so a region skips the trees of the sectors
+// around whose spheres are all far from it.
+// Most of the sleeping spheres of the indexed ring are out of reach of every awake sphere,
+// so they only go in the trees when they overlap the bounds of the awake spheres around.
+
+use crate::{
+    config::Topology,
+    constants::{MAX_SPHERE_COUNT, MAX_SPHERE_R, SECTOR_SIZE, SECTOR_WAKE_DISTANCE, WORLD_SIZE},
+    quad_tree::IdQuad,
+};
+
+// reach of a sector without awake spheres
+static NO_REACH: (f64, f64, f64, f64) = (f64::MAX, f64::MAX, f64::MIN, f64::MIN);
+
+pub struct Sectors {
+    // sectors per row and per column
+    pub cols: usize,
+    size: f64,
+    // spheres a sector can hold before spawning stops in it
+    budget: usize,
+    // ids of the spheres in every sector
+    members: Vec<Vec<usize>>,
+    // spheres in every sector, members plus the spawns since the last update
+    counts: Vec<usize>,
+    awake: Vec<bool>,
+    // awake, or next to an awake sector
+    indexed: Vec<bool>,
+    // only up to date in the indexed sectors
+    trees: Vec<IdQuad>,
+    // (left, top, right, bottom) around the awake spheres with their center in every sector
+    reach: Vec<(f64, f64, f64, f64)>,
+    // (left, top, right, bottom) around the spheres in the tree of every sector
+    bounds: Vec<(f64, f64, f64, f64)>,
+}
+
+impl Sectors {
+    pub fn new() -> Sectors {
+        let mut sectors = Sectors {
+            cols: 0,
+            size: 0.0,
+            budget: 0,
+            members: vec![],
+            counts: vec![],
+            awake: vec![],
+            indexed: vec![],
+            trees: vec![],
+            reach: vec![],
+            bounds: vec![],
+        };
+
+        sectors.resize(WORLD_SIZE);
+
+        return sectors;
+    }
+
+    pub fn len(&self) -> usize {
+        self.cols * self.cols
+    }
+
+    // split a world of the given size, returns true if the number of sectors changed.
+    // then every sector is empty and awake, and all the spheres have to be placed again.
+    // otherwise (a world growing or shrinking a little every update) only the sector size
+    // follows the world, the spheres keep their sectors and the trees take the new size
+    // when they are rebuilt
+    pub fn resize(&mut self, world_size: f64) -> bool {
+        let cols = usize::max(f64::ceil(world_size / SECTOR_SIZE) as usize, 1);
+
+        self.size = world_size / cols as f64;
+        self.budget = f64::ceil(
+            MAX_SPHERE_COUNT as f64 * (self.size * self.size) / (WORLD_SIZE * WORLD_SIZE),
+        ) as usize;
+
+        if cols == self.cols {
+            return false;
+        }
+
+        self.cols = cols;
+
+        let len = self.len();
+
+        self.members.clear();
+        self.members.resize_with(len, Vec::new);
+        self.counts.clear();
+        self.counts.resize(len, 0);
+        self.awake.clear();
+        self.awake.resize(len, true);
+        self.indexed.clear();
+        self.indexed.resize(len, true);
+        self.trees.clear();
+        self.trees
+            .resize_with(len, || IdQuad::new(0.0, 0.0, 0.0, 0.0));
+        self.reach.clear();
+        self.reach.resize(len, NO_REACH);
+        self.bounds.clear();
+        self.bounds.resize(len, NO_REACH);
+
+        return true;
+    }
+
+    // empty the lists of the indexed sectors, before placing their spheres again
+    pub fn clear_indexed(&mut self) {
+        for i in 0..self.len() {
+            if self.indexed[i] {
+                self.members[i].clear();
+            }
+        }
+    }
+
+    // empty the trees of the indexed sectors, call after update
+    pub fn clear_trees(&mut self) {
+        let tree_size = self.size + MAX_SPHERE_R * 2.0;
+
+        for i in 0..self.len() {
+            if self.indexed[i] {
+                let x = (i % self.cols) as f64 * self.size - MAX_SPHERE_R;
+                let y = (i / self.cols) as f64 * self.size - MAX_SPHERE_R;
+
+                self.trees[i].reset(x, y, tree_size, tree_size);
+            }
+        }
+
+        self.reach.fill(NO_REACH);
+        self.bounds.fill(NO_REACH);
+    }
+
+    // an awake sphere after it moved, call for all of them before insert
+    pub fn add_reach(&mut self, x: f64, y: f64, r: f64) {
+        let index = self.get_index(x, y);
+
+        self.reach[index] = get_union(self.reach[index], x, y, r);
+    }
+
+    // add a moved sphere to the tree of its sector, if an awake sphere can touch it
+    pub fn insert(&mut self, id: usize, x: f64, y: f64, r: f64, topology: Topology) {
+        let index = self.get_index(x, y);
+
+        if self.awake[index] || self.is_in_reach(index, x, y, r, topology) {
+            self.trees[index].insert(id, x - r, y - r, r * 2.0, r * 2.0);
+            self.bounds[index] = get_union(self.bounds[index], x, y, r);
+        }
+    }
+
+    // does the sphere overlap the reach of its sector or of the ones next to it
+    fn is_in_reach(&self, index: usize, x: f64, y: f64, r: f64, topology: Topology) -> bool {
+        let cols = self.cols as isize;
+        let world_size = self.size * self.cols as f64;
+        let (col, row) = ((index % self.cols) as isize, (index / self.cols) as isize);
+
+        for dy in -1..=1 {
+            for dx in -1..=1 {
+                let (mut c, mut r2, mut ox, mut oy) = (col + dx, row + dy, 0.0, 0.0);
+
+                if topology == Topology::TOROIDAL {
+                    // the reach of a sector across the edge, moved next to this one
+                    ox = c.div_euclid(cols) as f64 * world_size;
+                    oy = r2.div_euclid(cols) as f64 * world_size;
+                    c = c.rem_euclid(cols);
+                    r2 = r2.rem_euclid(cols);
+                } else if c < 0 || r2 < 0 || c >= cols || r2 >= cols {
+                    continue;
+                }
+
+                let (left, top, right, bottom) = self.reach[(r2 * cols + c) as usize];
+
+                if x + r >= left + ox
+                    && x - r <= right + ox
+                    && y + r >= top + oy
+                    && y - r <= bottom + oy
+                {
+                    return true;
+                }
+            }
+        }
+
+        return false;
+    }
+
+    // add the ids of the spheres that may overlap the region to out, from the trees of the
+    // indexed sectors and, if sleeping is set, the lists of the others.
+    // the region is not wrapped, the same id can be added more than once
+    pub fn add_ids_in_region(
+        &self,
+        x: f64,
+        y: f64,
+        w: f64,
+        h: f64,
+        sleeping: bool,
+        out: &mut Vec<usize>,
+    ) {
+        let (col_min, col_max) = match self.get_range(x, w) {
+            Some(range) => range,
+            None => return,
+        };
+        let (row_min, row_max) = match self.get_range(y, h) {
+            Some(range) => range,
+            None => return,
+        };
+
+        for row in row_min..=row_max {
+            for col in col_min..=col_max {
+                let index = row * self.cols + col;
+
+                // most trees in range only hold spheres far from the region
+                let (left, top, right, bottom) = self.bounds[index];
+
+                if self.indexed[index] && x + w >= left && x <= right && y + h >= top && y <= bottom
+                {
+                    self.trees[index].add_data_in_region(x, y, w, h, out);
+                }
+
+                // the trees of the ring only have the spheres in reach of the awake ones
+                if sleeping && !self.awake[index] {
+                    out.extend_from_slice(&self.members[index]);
+                }
+            }
+        }
+    }
+
+    // first and last sector along one axis whose tree can hold a sphere overlapping
+    // [start, start + len]
+    fn get_range(&self, start: f64, len: f64) -> Option<(usize, usize)> {
+        let max = self.cols as f64 - 1.0;
+        let first = f64::floor((start - MAX_SPHERE_R) / self.size);
+        let last = f64::floor((start + len + MAX_SPHERE_R) / self.size);
+
+        if last < 0.0 || first > max {
+            return None;
+        }
+
+        return Some((f64::max(first, 0.0) as usize, f64::min(last, max) as usize));
+    }
+
+    pub fn place(&mut self, id: usize, x: f64, y: f64) {
+        let index = self.get_index(x, y);
+        self.members[index].push(id);
+    }
+
+    // find the awake and indexed sectors from the positions of the real players,
+    // call once every sphere is placed
+    pub fn update(&mut self, topology: Topology, sleep: bool, players: &[(f64, f64)]) {
+        self.awake.fill(!sleep);
+        self.indexed.fill(!sleep);
+
+        for i in 0..self.len() {
+            self.counts[i] = self.members[i].len();
+        }
+
+        if !sleep {
+            return;
+        }
+
+        for (x, y) in players.iter() {
+            self.wake_around(self.get_index(*x, *y), topology);
+        }
+    }
+
+    // ids of the spheres in the awake sectors, or in the indexed ones
+    pub fn get_ids(&self, indexed: bool, out: &mut Vec<usize>) {
+        out.clear();
+
+        for i in 0..self.len() {
+            if self.awake[i] || (indexed && self.indexed[i]) {
+                out.extend_from_slice(&self.members[i]);
+            }
+        }
+    }
+
+    fn wake_around(&mut self, index: usize, topology: Topology) {
+        let distance = SECTOR_WAKE_DISTANCE as isize;
+
+        for index in self.get_neighbors(index, distance, topology) {
+            self.awake[index] = true;
+        }
+
+        for index in self.get_neighbors(index, distance + 1, topology) {
+            self.indexed[index] = true;
+        }
+    }
+
+    // sectors at most distance sectors away in both directions
+    fn get_neighbors(
+        &self,
+        index: usize,
+        distance: isize,
+        topology: Topology,
+    ) -> impl Iterator<Item = usize> {
+        let cols = self.cols as isize;
+        let (col, row) = ((index % self.cols) as isize, (index / self.cols) as isize);
+
+        (-distance..=distance)
+            .flat_map(move |dy| (-distance..=distance).map(move |dx| (col + dx, row + dy)))
+            .filter_map(move |(c, r)| {
+                if topology == Topology::TOROIDAL {
+                    return Some((r.rem_euclid(cols) * cols + c.rem_euclid(cols)) as usize);
+                }
+
+                if c < 0 || r < 0 || c >= cols || r >= cols {
+                    return None;
+                }
+
+                return Some((r * cols + c) as usize);
+            })
+    }
+
+    pub fn get_index(&self, x: f64, y: f64) -> usize {
+        let max = self.cols - 1;
+        let col = usize::min((f64::max(x, 0.0) / self.size) as usize, max);
+        let row = usize::min((f64::max(y, 0.0) / self.size) as usize, max);
+
+        return row * self.cols + col;
+    }
+
+    pub fn get_awake_count(&self) -> usize {
+        self.awake.iter().filter(|awake| **awake).count()
+    }
+
+    // false once the sector at (x, y) is full
+    pub fn has_budget(&self, x: f64, y: f64) -> bool {
+        self.counts[self.get_index(x, y)] < self.budget
+    }
+
+    // count a spawn, the sphere is placed on the next update
+    pub fn add(&mut self, x: f64, y: f64) {
+        let index = self.get_index(x, y);
+        self.counts[index] += 1;
+    }
+}
+
+// (left, top, right, bottom) grown to hold the sphere
+fn get_union(
+    (left, top, right, bottom): (f64, f64, f64, f64),
+    x: f64,
+    y: f64,
+    r: f64,
+) -> (f64, f64, f64, f64) {
+    return (
+        f64::min(left, x - r),
+        f64::min(top, y - r),
+        f64::max(right, x + r),
+        f64::max(bottom, y + r),
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Sectors;
+    use crate::{config::Topology, constants::SECTOR_SIZE};
+
+    #[test]
+    fn small_size_changes_keep_the_sectors() {
+        let mut sectors = Sectors::new();
+
+        assert!(sectors.resize(SECTOR_SIZE * 3.5));
+
+        let cols = sectors.cols;
+
+        sectors.place(0, SECTOR_SIZE * 3.4, SECTOR_SIZE * 0.5);
+
+        // a dynamic world changes its size a little every update
+        for i in 1..100 {
+            assert!(!sectors.resize(SECTOR_SIZE * 3.5 - i as f64 * 0.1));
+        }
+
+        assert_eq!(sectors.cols, cols);
+        assert_eq!(sectors.members[cols - 1], vec![0]);
+
+        assert!(sectors.resize(SECTOR_SIZE * 2.5));
+        assert!(sectors.members[cols - 1].is_empty());
+    }
+
+    #[test]
+    fn sleep_wakes_the_sectors_around_players() {
+        let mut sectors = Sectors::new();
+        sectors.resize(SECTOR_SIZE * 5.0);
+
+        let corner = [(SECTOR_SIZE * 0.5, SECTOR_SIZE * 0.5)];
+
+        sectors.update(Topology::BOUNDED, true, &corner);
+
+        assert_eq!(sectors.get_awake_count(), 4);
+        assert_eq!(sectors.indexed.iter().filter(|i| **i).count(), 9);
+
+        // across the edges of a toroidal world
+        sectors.update(Topology::TOROIDAL, true, &corner);
+
+        assert_eq!(sectors.get_awake_count(), 9);
+        assert_eq!(sectors.indexed.iter().filter(|i| **i).count(), 25);
+
+        sectors.update(Topology::BOUNDED, false, &[]);
+
+        assert_eq!(sectors.get_awake_count(), sectors.len());
+    }
+
+    #[test]
+    fn trees_only_hold_the_sleeping_spheres_in_reach() {
+        let mut sectors = Sectors::new();
+        sectors.resize(SECTOR_SIZE * 5.0);
+
+        // an awake sphere at the edge of the awake sectors, a sleeping one next to it
+        // and one further in the ring of indexed sectors
+        let spheres = [
+            (0, SECTOR_SIZE * 1.98, SECTOR_SIZE * 0.5, 20.0),
+            (1, SECTOR_SIZE * 2.02, SECTOR_SIZE * 0.5, 10.0),
+            (2, SECTOR_SIZE * 2.9, SECTOR_SIZE * 0.5, 10.0),
+        ];
+
+        for (id, x, y, _) in spheres.iter() {
+            sectors.place(*id, *x, *y);
+        }
+
+        sectors.update(
+            Topology::BOUNDED,
+            true,
+            &[(SECTOR_SIZE * 0.5, SECTOR_SIZE * 0.5)],
+        );
+        sectors.clear_trees();
+
+        let (_, x, y, r) = spheres[0];
+        sectors.add_reach(x, y, r);
+
+        for (id, x, y, r) in spheres.iter() {
+            sectors.insert(*id, *x, *y, *r, Topology::BOUNDED);
+        }
+
+        let mut ids = vec![];
+        let size = SECTOR_SIZE * 5.0;
+
+        sectors.add_ids_in_region(0.0, 0.0, size, size, false, &mut ids);
+        ids.sort();
+
+        assert_eq!(ids, vec![0, 1]);
+
+        // with sleeping, the lists of the sectors that are not awake too
+        ids.clear();
+        sectors.add_ids_in_region(0.0, 0.0, size, size, true, &mut ids);
+        ids.sort();
+        ids.dedup();
+
+        assert_eq!(ids, vec![0, 1, 2]);
+
+        // the region is far from every sphere in the trees of the sectors around it
+        ids.clear();
+        let (x, y) = (SECTOR_SIZE * 0.9, SECTOR_SIZE * 0.5);
+        sectors.add_ids_in_region(x, y, 5.0, 5.0, false, &mut ids);
+
+        assert!(ids.is_empty());
+    }
+}
@@ -0,0 +1,241 @@
+// Camera targets for watching the game without playing, after death or on the title screen.
+// The spectator picks a sphere to follow (or a free position) depending on the mode,
+// the Camera eases toward it so switching targets does not jump.
+// The auto director scores every player by the mass around it and the recent collisions
+// near it, and moves to a better spot only after watching the current one for a while.
+
+use wasm_bindgen::prelude::*;
+
+use crate::{
+    constants::{
+        DIRECTOR_ACTIVITY_CELLS, DIRECTOR_ACTIVITY_DECAY, DIRECTOR_ACTIVITY_WEIGHT,
+        DIRECTOR_MIN_HOLD_TIME, DIRECTOR_NEARBY_RADIUS, DIRECTOR_SWITCH_RATIO,
+        SPECTATOR_FREE_VIEW_AREA,
+    },
+    pool::Handle,
+    sphere::SphereType,
+    world::World,
+};
+
+#[wasm_bindgen]
+#[derive(PartialEq, Clone, Copy)]
+pub enum SpectatorMode {
+    // follow the best player
+    LEADER,
+    // follow a player picked with next / prev
+    CYCLE,
+    // stay at the free position
+    FREE,
+    // follow whatever looks interesting
+    DIRECTOR,
+}
+
+pub struct Spectator {
+    pub mode: SpectatorMode,
+    // followed sphere
+    target: Option<Handle>,
+    free_x: f64,
+    free_y: f64,
+    free_view_area: f64,
+    // recent collisions, DIRECTOR_ACTIVITY_CELLS * DIRECTOR_ACTIVITY_CELLS cells over the world
+    activity: Vec<f64>,
+    // seconds the director has been following the current target
+    hold_time: f64,
+    candidates: Vec<usize>,
+}
+
+impl Spectator {
+    pub fn new(x: f64, y: f64) -> Spectator {
+        Spectator {
+            mode: SpectatorMode::DIRECTOR,
+            target: None,
+            free_x: x,
+            free_y: y,
+            free_view_area: SPECTATOR_FREE_VIEW_AREA,
+            activity: vec![0.0; DIRECTOR_ACTIVITY_CELLS * DIRECTOR_ACTIVITY_CELLS],
+            hold_time: 0.0,
+            candidates: vec![],
+        }
+    }
+
+    pub fn set_mode(&mut self, mode: SpectatorMode) {
+        self.mode = mode;
+        self.hold_time = 0.0;
+    }
+
+    pub fn set_free_view(&mut self, x: f64, y: f64, view_area: f64) {
+        self.free_x = x;
+        self.free_y = y;
+        self.free_view_area = view_area;
+    }
+
+    // follow the player ranked one below (step 1) or above (step -1) the current target
+    pub fn cycle(&mut self, world: &World, step: isize) {
+        self.mode = SpectatorMode::CYCLE;
+
+        let ids = world.leaderboard.get_ids();
+
+        if ids.len() == 0 {
+            self.target = None;
+            return;
+        }
+
+        let index = match self.get_target_id(world) {
+            Some(id) => {
+                let rank = world.spheres.at(id).rank as isize;
+
+                (rank - 1 + step).rem_euclid(ids.len() as isize) as usize
+            }
+            None => 0,
+        };
+
+        self.target = Some(world.spheres.handle(ids[index]));
+    }
+
+    // the followed sphere, if it is still alive
+    pub fn get_target(&self, world: &World) -> Option<Handle> {
+        let handle = self.target?;
+        let sphere = world.spheres.get(handle)?;
+
+        if sphere.r <= 0.0 || sphere.r#type != SphereType::PLAYER {
+            return None;
+        }
+
+        return Some(handle);
+    }
+
+    pub fn get_target_id(&self, world: &World) -> Option<usize> {
+        Some(self.get_target(world)?.index)
+    }
+
+    // (x, y, view area) of SpectatorMode::FREE, and of the other modes when there is
+    // nobody to follow
+    pub fn get_free_view(&self) -> (f64, f64, f64) {
+        (self.free_x, self.free_y, self.free_view_area)
+    }
+
+    // call after World::update, then point the camera at get_target or get_free_view
+    pub fn update(&mut self, world: &World, dt: f64) {
+        self.update_activity(world, dt);
+        self.hold_time += dt;
+
+        match self.mode {
+            SpectatorMode::LEADER => {
+                self.target = world
+                    .leaderboard
+                    .get_top(1)
+                    .first()
+                    .map(|id| world.spheres.handle(*id));
+            }
+            SpectatorMode::CYCLE => {
+                if self.get_target_id(world).is_none() {
+                    self.cycle(world, 0);
+                }
+            }
+            SpectatorMode::FREE => {
+                self.target = None;
+            }
+            SpectatorMode::DIRECTOR => {
+                self.direct(world);
+            }
+        }
+    }
+
+    fn update_activity(&mut self, world: &World, dt: f64) {
+        let decay = f64::exp(-DIRECTOR_ACTIVITY_DECAY * dt);
+
+        for cell in self.activity.iter_mut() {
+            *cell *= decay;
+        }
+
+        for (x, y) in world.collision_points.iter() {
+            let index = Spectator::get_cell_index(world.size, *x, *y);
+            self.activity[index] += 1.0;
+        }
+    }
+
+    fn get_cell_index(size: f64, x: f64, y: f64) -> usize {
+        let cells = DIRECTOR_ACTIVITY_CELLS as f64;
+        let max = DIRECTOR_ACTIVITY_CELLS - 1;
+        let col = usize::min((f64::max(x, 0.0) / size * cells) as usize, max);
+        let row = usize::min((f64::max(y, 0.0) / size * cells) as usize, max);
+
+        return row * DIRECTOR_ACTIVITY_CELLS + col;
+    }
+
+    fn direct(&mut self, world: &World) {
+        let current = self.get_target_id(world);
+
+        // only look around when it is time to switch, or the target is gone
+        if current.is_some() && self.hold_time < DIRECTOR_MIN_HOLD_TIME {
+            return;
+        }
+
+        let mut best: Option<(usize, f64)> = None;
+        let mut current_score = 0.0;
+
+        for i in 0..world.leaderboard.len() {
+            let id = world.leaderboard.get_ids()[i];
+
+            // died this update, freed at the end of it
+            if world.spheres.at(id).r <= 0.0 {
+                continue;
+            }
+
+            let score = self.get_interest(world, id);
+
+            if Some(id) == current {
+                current_score = score;
+            }
+
+            if best.is_none_or(|(_, best_score)| score > best_score) {
+                best = Some((id, score));
+            }
+        }
+
+        if let Some((id, score)) = best {
+            if current.is_none() || score > current_score * DIRECTOR_SWITCH_RATIO {
+                self.target = Some(world.spheres.handle(id));
+                self.hold_time = 0.0;
+            }
+        }
+    }
+
+    // mass of the players around the sphere plus the recent collisions under it
+    fn get_interest(&mut self, world: &World, id: usize) -> f64 {
+        let sphere = world.spheres.at(id);
+        let radius = DIRECTOR_NEARBY_RADIUS + sphere.r;
+
+        world.get_ids_in_region(
+            sphere.x - radius,
+            sphere.y - radius,
+            radius * 2.0,
+            radius * 2.0,
+            &mut self.candidates,
+        );
+
+        self.candidates.sort_unstable();
+        self.candidates.dedup();
+
+        let mut mass = 0.0;
+
+        for other_id in self.candidates.iter() {
+            let other = world.spheres.at(*other_id);
+
+            if other.r#type != SphereType::PLAYER || other.r <= 0.0 {
+                continue;
+            }
+
+            let dx = world.delta(sphere.x, other.x);
+            let dy = world.delta(sphere.y, other.y);
+
+            if dx * dx + dy * dy <= radius * radius {
+                mass += other.r * other.r;
+            }
+        }
+
+        let activity = self.activity[Spectator::get_cell_index(world.size, sphere.x, sphere.y)];
+
+        return mass + activity * DIRECTOR_ACTIVITY_WEIGHT;
+    }
+}
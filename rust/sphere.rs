@@ -1,8 +1,14 @@
+use crate::config::{MotionProfile, PhysicsModel, Topology};
 use crate::constants::{
-    MAX_SPHERE_R, MAX_SPHERE_SPEED, R_DECREASE_RATIO, SHOOT_AREA_RATIO, SHOOT_DELAY, SHOOT_FORCE,
-    WORLD_SIZE,
+    BULLET_HIT_CREDIT_TIME, MAX_SPHERE_R, MAX_SPHERE_SPEED, MOVE_ACCELERATION,
+    MOVE_ACCELERATION_EXPONENT, MOVE_SLOW_RADIUS, R_DECREASE_RATIO, SHOOT_AREA_RATIO, SHOOT_DELAY,
+    STARTING_PLAYER_R,
 };
+use crate::pool::Handle;
+use crate::utils::wrap_delta;
+use wasm_bindgen::prelude::*;
 
+#[wasm_bindgen]
 #[repr(u8)]
 #[derive(PartialEq, Clone, Copy)]
 pub enum SphereType {
@@ -10,6 +16,7 @@ pub enum SphereType {
     FOOD = 1,
     AM = 2,
     BULLET = 3,
+    VIRUS = 4,
 }
 
 #[derive(PartialEq, Clone, Copy)]
@@ -21,11 +28,24 @@ pub struct Sphere {
     pub r: f64,
     pub color: u32,
     pub r#type: SphereType,
-    pub shooter_id: Option<usize>,
+    pub shooter_id: Option<Handle>,
     pub shoot_delay: f64,
     pub rank: usize,
+    // stats of the current life, only used by players
+    pub peak_score: usize,
+    pub time_alive: f64,
+    pub kills: usize,
+    // shooter of the last bullet that hit the sphere, the shooter gets the kill if the
+    // sphere dies before last_hit_time runs out
+    pub last_hit_by: Option<Handle>,
+    pub last_hit_time: f64,
+    // touching an anti matter sphere in this and the previous update
+    pub touching_am: bool,
+    pub was_touching_am: bool,
     pub is_fake: bool,
     pub name: Option<usize>,
+    // point the sphere steers toward, only used by ControlScheme::MOVE
+    pub move_target: Option<(f64, f64)>,
 
     // unique id, for now we use usize.
     // I want to use u64, but JS does not support u64, and usize should be sufficient enough
@@ -54,10 +74,18 @@ impl Sphere {
             r#type,
             shoot_delay: 0.0,
             rank: 0,
+            peak_score: 0,
+            time_alive: 0.0,
+            kills: 0,
+            last_hit_by: None,
+            last_hit_time: 0.0,
+            touching_am: false,
+            was_touching_am: false,
             uid: 0,
             is_fake,
             name,
             shooter_id: None,
+            move_target: None,
         }
     }
 
@@ -73,9 +101,17 @@ impl Sphere {
             shoot_delay: 0.0,
             uid: 0,
             rank: 0,
+            peak_score: 0,
+            time_alive: 0.0,
+            kills: 0,
+            last_hit_by: None,
+            last_hit_time: 0.0,
+            touching_am: false,
+            was_touching_am: false,
             is_fake: false,
             name: None,
             shooter_id: None,
+            move_target: None,
         }
     }
 
@@ -104,24 +140,65 @@ impl Sphere {
         self.name = name;
         self.is_fake = is_fake;
         self.shooter_id = None;
+        self.move_target = None;
+        self.reset_stats();
     }
 
-    pub fn set_shooter(&mut self, shooter_id: usize) {
+    pub fn reset_stats(&mut self) {
+        self.peak_score = 0;
+        self.time_alive = 0.0;
+        self.kills = 0;
+        self.last_hit_by = None;
+        self.last_hit_time = 0.0;
+        self.touching_am = false;
+        self.was_touching_am = false;
+    }
+
+    pub fn set_shooter(&mut self, shooter_id: Handle) {
         self.shooter_id = Some(shooter_id);
     }
 
+    pub fn set_last_hit(&mut self, shooter_id: Handle) {
+        self.last_hit_by = Some(shooter_id);
+        self.last_hit_time = BULLET_HIT_CREDIT_TIME;
+    }
+
     pub fn reset_shoot_delay(&mut self) {
         self.shoot_delay = SHOOT_DELAY;
     }
 
-    pub fn update(&mut self, dt: f64) {
+    // with limit_speed false the top speed of the profile is not enforced, only the drag
+    // slows the sphere down
+    pub fn update(&mut self, dt: f64, motion: &MotionProfile, limit_speed: bool) {
         self.shoot_delay = f64::max(self.shoot_delay - dt, 0.0);
+        self.last_hit_time = f64::max(self.last_hit_time - dt, 0.0);
 
         self.r -= R_DECREASE_RATIO * self.r * dt;
 
+        let speed_sq = self.vx * self.vx + self.vy * self.vy;
+
+        if speed_sq > 0.0 {
+            let speed = f64::sqrt(speed_sq);
+
+            // implicit integration of the drag, so a big dt can not reverse the velocity
+            let drag = (motion.linear_drag + motion.quadratic_drag * speed) * dt;
+            let mut new_speed = speed / (1.0 + drag);
+
+            if limit_speed {
+                new_speed = f64::min(new_speed, motion.get_max_speed(self.r));
+            }
+
+            if new_speed != speed {
+                self.vx = (self.vx / speed) * new_speed;
+                self.vy = (self.vy / speed) * new_speed;
+            }
+        }
+
         self.x += self.vx * dt;
         self.y += self.vy * dt;
+    }
 
+    pub fn bounce(&mut self, size: f64) {
         let left = self.x - self.r;
         let right = self.x + self.r;
         let top = self.y - self.r;
@@ -134,8 +211,8 @@ impl Sphere {
             self.shooter_id = None;
         }
 
-        if right > WORLD_SIZE {
-            let rem = right - WORLD_SIZE;
+        if right > size {
+            let rem = right - size;
 
             self.x -= rem * 2.0;
             self.vx *= -1.0;
@@ -150,8 +227,8 @@ impl Sphere {
             self.shooter_id = None;
         }
 
-        if bottom > WORLD_SIZE {
-            let rem = bottom - WORLD_SIZE;
+        if bottom > size {
+            let rem = bottom - size;
 
             self.y -= rem * 2.0;
             self.vy *= -1.0;
@@ -160,9 +237,90 @@ impl Sphere {
         }
     }
 
-    pub fn shoot(&mut self, dirx: f64, diry: f64) {
-        self.vx -= dirx * SHOOT_FORCE;
-        self.vy -= diry * SHOOT_FORCE;
+    pub fn bounce_circle(&mut self, cx: f64, cy: f64, radius: f64) {
+        let dx = self.x - cx;
+        let dy = self.y - cy;
+        let distance = f64::sqrt(dx * dx + dy * dy);
+        let depth = distance + self.r - radius;
+
+        if depth <= 0.0 || distance == 0.0 {
+            return;
+        }
+
+        // the surface normal points back to the center
+        self.bounce_off(-dx / distance, -dy / distance, depth);
+    }
+
+    pub fn wrap(&mut self, size: f64) {
+        self.x = f64::rem_euclid(self.x, size);
+        self.y = f64::rem_euclid(self.y, size);
+    }
+
+    // accelerate toward move_target, bigger spheres accelerate slower.
+    // in a toroidal world of the given size the target is reached the short way round
+    pub fn steer(&mut self, dt: f64, motion: &MotionProfile, topology: Topology, size: f64) {
+        let (tx, ty) = match self.move_target {
+            Some(target) => target,
+            None => return,
+        };
+
+        let (dx, dy) = match topology {
+            Topology::BOUNDED | Topology::CIRCULAR => (tx - self.x, ty - self.y),
+            Topology::TOROIDAL => (wrap_delta(self.x, tx, size), wrap_delta(self.y, ty, size)),
+        };
+        let distance = f64::sqrt(dx * dx + dy * dy);
+
+        let max_speed = f64::min(motion.get_max_speed(self.r), MAX_SPHERE_SPEED);
+        let speed = max_speed * f64::min(distance / MOVE_SLOW_RADIUS, 1.0);
+
+        let (desired_vx, desired_vy) = if distance > 0.0 {
+            ((dx / distance) * speed, (dy / distance) * speed)
+        } else {
+            (0.0, 0.0)
+        };
+
+        let acceleration =
+            MOVE_ACCELERATION * f64::powf(STARTING_PLAYER_R / self.r, MOVE_ACCELERATION_EXPONENT);
+        let max_change = acceleration * dt;
+
+        let change_x = desired_vx - self.vx;
+        let change_y = desired_vy - self.vy;
+        let change = f64::sqrt(change_x * change_x + change_y * change_y);
+
+        if change <= max_change {
+            self.vx = desired_vx;
+            self.vy = desired_vy;
+        } else {
+            self.vx += (change_x / change) * max_change;
+            self.vy += (change_y / change) * max_change;
+        }
+    }
+
+    // push the sphere out along the normal and reflect the velocity, like the wall bounce
+    pub fn bounce_off(&mut self, nx: f64, ny: f64, depth: f64) {
+        self.x += nx * depth;
+        self.y += ny * depth;
+
+        let dot = self.vx * nx + self.vy * ny;
+
+        if dot < 0.0 {
+            self.vx -= 2.0 * dot * nx;
+            self.vy -= 2.0 * dot * ny;
+        }
+
+        self.shooter_id = None;
+    }
+
+    // the recoil is capped at MAX_SPHERE_SPEED, except in PhysicsModel::MOMENTUM
+    // where it has to match the momentum the bullet carries away
+    pub fn shoot(&mut self, dirx: f64, diry: f64, force: f64, physics: PhysicsModel) {
+        self.vx -= dirx * force;
+        self.vy -= diry * force;
+        self.r *= SHOOT_AREA_RATIO;
+
+        if physics == PhysicsModel::MOMENTUM {
+            return;
+        }
 
         let speed_sq = self.vx * self.vx + self.vy * self.vy;
 
@@ -171,8 +329,22 @@ impl Sphere {
             self.vx = (self.vx / speed) * MAX_SPHERE_SPEED;
             self.vy = (self.vy / speed) * MAX_SPHERE_SPEED;
         }
+    }
+
+    // area is used as mass, the gained mass brings the velocity of the sphere it came from.
+    // call this before setting the new radius
+    pub fn gain_momentum(&mut self, new_r: f64, vx: f64, vy: f64) {
+        let mass = self.r * self.r;
+        let new_mass = new_r * new_r;
+
+        if new_mass <= mass {
+            return;
+        }
 
-        self.r *= SHOOT_AREA_RATIO;
+        let gained = new_mass - mass;
+
+        self.vx = (self.vx * mass + vx * gained) / new_mass;
+        self.vy = (self.vy * mass + vy * gained) / new_mass;
     }
 
     pub fn absorb(s1: &Sphere, s2: &Sphere, eater: &Sphere, distance_sq: f64) -> (f64, f64) {
@@ -228,3 +400,42 @@ impl Sphere {
         f64::trunc(4.0 * self.r * self.r) as usize
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{Sphere, SphereType};
+    use crate::config::{MotionProfile, Topology};
+
+    // a sphere next to the right edge of a 1000 wide world, steered for one step toward
+    // a point just across the edge
+    fn steer_across_edge(topology: Topology) -> Sphere {
+        let mut sphere = Sphere::new(
+            995.0,
+            500.0,
+            0.0,
+            0.0,
+            10.0,
+            0,
+            SphereType::PLAYER,
+            None,
+            false,
+        );
+        sphere.move_target = Some((5.0, 500.0));
+        sphere.steer(1.0 / 60.0, &MotionProfile::free(), topology, 1000.0);
+
+        sphere
+    }
+
+    #[test]
+    fn steer_takes_the_short_way_across_the_toroidal_seam() {
+        let sphere = steer_across_edge(Topology::TOROIDAL);
+
+        assert!(sphere.vx > 0.0);
+        assert_eq!(sphere.vy, 0.0);
+    }
+
+    #[test]
+    fn steer_does_not_wrap_in_a_bounded_world() {
+        assert!(steer_across_edge(Topology::BOUNDED).vx < 0.0);
+    }
+}
@@ -0,0 +1,66 @@
+// The public state of a sphere in one object, for JS. It is a copy taken when it is
+// requested, so it does not follow the sphere after the next update.
+// JS has to call free() on it (or let the FinalizationRegistry of the bindings do it).
+
+use wasm_bindgen::prelude::*;
+
+use crate::{
+    pool::Handle,
+    sphere::{Sphere, SphereType},
+};
+
+#[wasm_bindgen]
+#[derive(Clone, Copy)]
+pub struct SphereView {
+    // slot id, only valid until the next update
+    pub id: usize,
+    // the handle can be kept, see Ubur::get_sphere_id
+    pub handle: f64,
+    pub uid: usize,
+    #[wasm_bindgen(js_name = "type")]
+    pub r#type: SphereType,
+    pub x: f64,
+    pub y: f64,
+    pub vx: f64,
+    pub vy: f64,
+    pub r: f64,
+    pub score: usize,
+    pub color: u32,
+    // index into the fake player names, none for real players and other types
+    pub name: Option<usize>,
+    // 1 is the best, 0 if the sphere is not ranked
+    pub rank: usize,
+    // status
+    pub is_fake: bool,
+    // seconds until the sphere can shoot again
+    pub shoot_delay: f64,
+    pub touching_am: bool,
+    // stats of the current life, only used by players
+    pub kills: usize,
+    pub time_alive: f64,
+}
+
+impl SphereView {
+    pub fn new(id: usize, handle: Handle, sphere: &Sphere) -> SphereView {
+        SphereView {
+            id,
+            handle: handle.to_f64(),
+            uid: sphere.uid,
+            r#type: sphere.r#type,
+            x: sphere.x,
+            y: sphere.y,
+            vx: sphere.vx,
+            vy: sphere.vy,
+            r: sphere.r,
+            score: sphere.get_score(),
+            color: sphere.color,
+            name: sphere.name,
+            rank: sphere.rank,
+            is_fake: sphere.is_fake,
+            shoot_delay: f64::max(sphere.shoot_delay, 0.0),
+            touching_am: sphere.touching_am,
+            kills: sphere.kills,
+            time_alive: sphere.time_alive,
+        }
+    }
+}
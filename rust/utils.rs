@@ -1,5 +1,7 @@
+#[cfg(target_arch = "wasm32")]
 use wasm_bindgen::prelude::*;
 
+#[cfg(target_arch = "wasm32")]
 #[wasm_bindgen]
 extern "C" {
     #[wasm_bindgen(js_namespace = console)]
@@ -7,6 +9,51 @@ extern "C" {
 
     #[wasm_bindgen(js_namespace = Math)]
     pub fn random() -> f64;
+
+    // milliseconds since the unix epoch
+    #[wasm_bindgen(js_namespace = Date)]
+    pub fn now() -> f64;
+}
+
+// the native build (e.g. a server) has no JS to call, so use the std equivalents
+#[cfg(not(target_arch = "wasm32"))]
+pub fn log(s: &str) {
+    println!("{}", s);
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+thread_local! {
+    static RANDOM_STATE: std::cell::Cell<u64> = std::cell::Cell::new(now() as u64 | 1);
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+pub fn random() -> f64 {
+    // xorshift64*, good enough for spawning spheres
+    RANDOM_STATE.with(|state| {
+        let mut x = state.get();
+        x ^= x >> 12;
+        x ^= x << 25;
+        x ^= x >> 27;
+        state.set(x);
+
+        (x.wrapping_mul(0x2545f4914f6cdd1d) >> 11) as f64 / (1u64 << 53) as f64
+    })
+}
+
+// restart random() of this thread from a fixed point, so a run can be repeated
+#[cfg(not(target_arch = "wasm32"))]
+pub fn seed_random(seed: u64) {
+    RANDOM_STATE.with(|state| state.set(seed | 1));
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+pub fn now() -> f64 {
+    use std::time::{SystemTime, UNIX_EPOCH};
+
+    match SystemTime::now().duration_since(UNIX_EPOCH) {
+        Ok(duration) => duration.as_secs_f64() * 1000.0,
+        Err(_) => 0.0,
+    }
 }
 
 pub fn rand(min: f64, max: f64) -> f64 {
@@ -18,7 +65,7 @@ pub fn rand_int(min: i32, max: i32) -> i32 {
 }
 
 pub fn next_power_of_2(value: usize) -> usize {
-    if value <= 0 {
+    if value == 0 {
         return 1;
     };
 
@@ -35,6 +82,29 @@ pub fn next_power_of_2(value: usize) -> usize {
     return result;
 }
 
+// shortest signed distance from a to b on a loop of the given size
+pub fn wrap_delta(a: f64, b: f64, size: f64) -> f64 {
+    let d = b - a;
+
+    if d > size * 0.5 {
+        return d - size;
+    }
+
+    if d < -size * 0.5 {
+        return d + size;
+    }
+
+    return d;
+}
+
+// (width, height) of a view with the aspect ratio and area
+pub fn get_view_size(aspect_ratio: f64, view_area: f64) -> (f64, f64) {
+    let h = f64::sqrt(view_area / aspect_ratio);
+    let w = aspect_ratio * h;
+
+    return (w, h);
+}
+
 pub fn rand_u32(min: u32, max: u32) -> u32 {
     (random() * (max - min) as f64 + min as f64) as u32
 }
@@ -71,3 +141,14 @@ pub fn darken_color(color: u32, multiplier: f64) -> u32 {
 //     color = (color / 0x100) | 0;
 //   }
 // }
+
+// (x, y) scaled to length 1, none for a zero or non finite vector
+pub fn get_direction(x: f64, y: f64) -> Option<(f64, f64)> {
+    let len = f64::sqrt(x * x + y * y);
+
+    if !len.is_finite() || len == 0.0 {
+        return None;
+    }
+
+    return Some((x / len, y / len));
+}
@@ -1,61 +1,190 @@
+use std::collections::HashMap;
+
 use crate::{
+    config::{ControlScheme, PhysicsModel, Topology, WorldConfig},
     constants::{
         AM_SPAWN_DELAY, AM_SPAWN_R_MAX, AM_SPAWN_R_MIN, BULLET_AREA_RATIO, BULLET_SPEED,
-        FAKE_NAME_LEN, FOOD_SPAWN_DELAY, FOOD_SPAWN_R_MAX, FOOD_SPAWN_R_MIN, MAX_SPHERE_COUNT,
-        MAX_SPHERE_SPEED, SPHERE_COLOR_MAX, SPHERE_COLOR_MIN, STARTING_PLAYER_R,
-        STARTING_PLAYER_R_RANDOMNESS, WORLD_SIZE,
+        FAKE_NAME_LEN, FAKE_PLAYER_NAMES, FOOD_SPAWN_DELAY, FOOD_SPAWN_R_MAX, FOOD_SPAWN_R_MIN,
+        MAX_SPAWN_ATTEMPTS, MAX_SPAWN_CATCH_UP, MAX_SPHERE_CAPACITY, MAX_SPHERE_COUNT,
+        MAX_SPHERE_SPEED, SHOOT_AREA_RATIO_SQ, SHOOT_FORCE, SPHERE_COLOR_MAX, SPHERE_COLOR_MIN,
+        STARTING_PLAYER_R, STARTING_PLAYER_R_RANDOMNESS, VIRUS_BURST_AREA_RATIO, VIRUS_BURST_MIN_R,
+        VIRUS_BURST_PIECES, VIRUS_BURST_SPEED, VIRUS_COLOR, VIRUS_SPAWN_DELAY, VIRUS_SPAWN_R_MAX,
+        VIRUS_SPAWN_R_MIN, WORLD_AREA_PER_PLAYER, WORLD_RESIZE_SPEED, WORLD_SIZE, WORLD_SIZE_MAX,
+        WORLD_SIZE_MIN,
     },
-    pool::Pool,
-    quad_tree::IdQuad,
+    events::WorldEvent,
+    input::{Input, InputError, InputQueue},
+    leaderboard::Leaderboard,
+    obstacle::Obstacle,
+    parallel::{self, get_chunk_len, get_thread_count},
+    pool::{Handle, Pool, PoolError},
+    sector::Sectors,
     sphere::{Sphere, SphereType},
-    utils::{darken_color, rand, rand_color, rand_int},
+    utils::{darken_color, get_direction, rand, rand_color, rand_int, wrap_delta},
 };
 
+#[derive(Clone, Copy)]
 pub enum Command {
-    Shoot(usize, f64, f64),
+    Shoot(Handle, f64, f64),
+    // sphere, target x, target y
+    Move(Handle, f64, f64),
+}
+
+impl Command {
+    pub fn is_valid(&self) -> bool {
+        match *self {
+            Command::Shoot(_, dirx, diry) => get_direction(dirx, diry).is_some(),
+            Command::Move(_, x, y) => x.is_finite() && y.is_finite(),
+        }
+    }
+}
+
+#[derive(PartialEq, Clone, Copy, Debug)]
+pub enum SpawnError {
+    // the sphere pool is at its max size
+    FULL,
+    // no free spot found in MAX_SPAWN_ATTEMPTS tries
+    NOROOM,
+}
+
+impl From<PoolError> for SpawnError {
+    fn from(error: PoolError) -> SpawnError {
+        match error {
+            PoolError::FULL => SpawnError::FULL,
+        }
+    }
 }
 
 pub struct World {
+    pub config: WorldConfig,
+    // current width and height of the world, it only changes when config.dynamic_size is set
+    pub size: f64,
     pub spheres: Pool<Sphere>,
+    // the spheres in indexed sectors, the ones that take part in the current update.
+    // without config.sleep every sphere is, otherwise the leaderboard only ranks these
     pub sphere_ids: Vec<usize>,
-    pub commands: Vec<Command>,
+    // the spheres of sphere_ids in awake sectors, the only ones that move and collide
+    pub awake_ids: Vec<usize>,
+    // spheres obtained since the last update, they are placed in their sectors by the next one
+    spawned_ids: Vec<usize>,
+    // positions of the real players, they keep the sectors around them awake
+    player_positions: Vec<(f64, f64)>,
+    pub sectors: Sectors,
+    pub inputs: InputQueue,
+    due_inputs: Vec<Input>,
+    // the tick the next update processes
+    pub tick: u32,
+    pub obstacles: Vec<Obstacle>,
     food_spawn_counter: f64,
     am_spawn_counter: f64,
+    virus_spawn_counter: f64,
     current_uid: usize,
-    qt: IdQuad,
     qt_check_ids: Vec<usize>,
-    pub highscore_player_ids: Vec<usize>,
+    // (sphere id, other sphere id) pairs that may collide, for every chunk of awake_ids
+    collision_candidates: Vec<Vec<(usize, usize)>>,
+    // quad tree results of every chunk
+    candidate_queries: Vec<Vec<usize>>,
+    // is the sphere of that slot in awake_ids, to split the awake spheres over threads
+    awake_mask: Vec<bool>,
+    // threads used for the motion and the collision candidates,
+    // anything above 1 only helps with the parallel feature
+    pub threads: usize,
+    // (sphere id, virus id) pairs collected during collision check
+    virus_bursts: Vec<(usize, usize)>,
+    // release the unused sphere slots on the next update
+    shrink_requested: bool,
+    pub leaderboard: Leaderboard,
+    pub events: Vec<WorldEvent>,
+    // where spheres of players, anti matter or viruses collided in the last update
+    pub collision_points: Vec<(f64, f64)>,
+    // names of the real players, by uid
+    player_names: HashMap<usize, String>,
 }
 
 impl World {
     pub fn new() -> World {
+        World::with_config(WorldConfig::new())
+    }
+
+    pub fn with_config(config: WorldConfig) -> World {
+        let mut spheres = Pool::new(Sphere::zero, MAX_SPHERE_COUNT);
+        spheres.set_max_size(Some(MAX_SPHERE_CAPACITY));
+
         World {
-            spheres: Pool::new(Sphere::zero, MAX_SPHERE_COUNT),
+            config,
+            size: WORLD_SIZE,
+            spheres,
             sphere_ids: Vec::with_capacity(MAX_SPHERE_COUNT),
-            commands: vec![],
+            awake_ids: Vec::with_capacity(MAX_SPHERE_COUNT),
+            spawned_ids: vec![],
+            player_positions: vec![],
+            sectors: Sectors::new(),
+            inputs: InputQueue::new(),
+            due_inputs: vec![],
+            tick: 0,
+            obstacles: vec![],
             food_spawn_counter: 0.0,
             am_spawn_counter: 0.0,
+            virus_spawn_counter: 0.0,
             current_uid: 0,
-            highscore_player_ids: Vec::with_capacity(120),
-            qt: IdQuad::new(0.0, 0.0, WORLD_SIZE, WORLD_SIZE),
+            leaderboard: Leaderboard::new(120),
+            events: vec![],
+            collision_points: vec![],
+            player_names: HashMap::new(),
             qt_check_ids: vec![],
+            virus_bursts: vec![],
+            shrink_requested: false,
+            collision_candidates: vec![],
+            candidate_queries: vec![],
+            awake_mask: vec![],
+            threads: get_thread_count(),
         }
     }
 
+    // a bigger world starts with more spheres, for the same density as the classic one
     pub fn init(&mut self) {
-        for _ in 0..10 {
+        let density = self.get_density();
+
+        for _ in 0..(10.0 * density) as usize {
             self.spawn_am();
         }
 
-        for _ in 0..500 {
+        for _ in 0..(5.0 * density) as usize {
+            self.spawn_virus();
+        }
+
+        for _ in 0..(500.0 * density) as usize {
             self.spawn_food();
         }
 
-        for _ in 0..99 {
-            self.spawn_fake_player();
+        for _ in 0..(99.0 * density) as usize {
+            let _ = self.spawn_fake_player();
         }
     }
 
+    // width and height of the world, call before init. with config.dynamic_size
+    // the size keeps changing with the number of players
+    pub fn set_size(&mut self, size: f64) {
+        self.size = size;
+        self.resize_sectors();
+    }
+
+    // area of the world compared to the classic one
+    fn get_density(&self) -> f64 {
+        (self.size * self.size) / (WORLD_SIZE * WORLD_SIZE)
+    }
+
+    // size limit of the sphere pool, never below MAX_SPHERE_CAPACITY so a shrinking
+    // world does not lose the spheres it has
+    fn get_max_spheres(&self) -> usize {
+        (MAX_SPHERE_CAPACITY as f64 * f64::max(self.get_density(), 1.0)) as usize
+    }
+
+    // give back the memory of the sphere slots freed after a busy moment
+    pub fn shrink_to_fit(&mut self) {
+        self.shrink_requested = true;
+    }
+
     pub fn increment_uid(&mut self) {
         if self.current_uid == usize::MAX {
             self.current_uid = 0;
@@ -65,128 +194,477 @@ impl World {
     }
 
     pub fn update(&mut self, dt: f64) {
-        // spawn food and am
-        self.food_spawn_counter += dt;
-        self.am_spawn_counter += dt;
+        self.events.clear();
+        self.collision_points.clear();
 
-        if self.food_spawn_counter >= FOOD_SPAWN_DELAY {
-            self.food_spawn_counter -= FOOD_SPAWN_DELAY;
+        if self.config.dynamic_size {
+            self.update_size(dt);
+        }
 
-            if self.spheres.len() < MAX_SPHERE_COUNT {
-                self.spawn_food();
-            }
+        // spawn food, am and virus, a bigger world spawns them faster to keep the same density.
+        // every sector stops spawning once it holds its share of MAX_SPHERE_COUNT
+        let spawn_dt = f64::min(dt, MAX_SPAWN_CATCH_UP) * self.get_density();
+
+        self.food_spawn_counter += spawn_dt;
+        self.am_spawn_counter += spawn_dt;
+        self.virus_spawn_counter += spawn_dt;
+
+        while self.food_spawn_counter >= FOOD_SPAWN_DELAY {
+            self.food_spawn_counter -= FOOD_SPAWN_DELAY;
+            self.spawn_food();
         }
 
-        if self.am_spawn_counter >= AM_SPAWN_DELAY {
+        while self.am_spawn_counter >= AM_SPAWN_DELAY {
             self.am_spawn_counter -= AM_SPAWN_DELAY;
+            self.spawn_am();
+        }
 
-            if self.spheres.len() < MAX_SPHERE_COUNT {
-                self.spawn_am();
-            }
+        while self.virus_spawn_counter >= VIRUS_SPAWN_DELAY {
+            self.virus_spawn_counter -= VIRUS_SPAWN_DELAY;
+            self.spawn_virus();
         }
 
-        // handle commands
-        for i in 0..self.commands.len() {
-            let command = &self.commands[i];
+        // handle inputs
+        self.inputs.take_due(self.tick, &mut self.due_inputs);
+
+        for i in 0..self.due_inputs.len() {
+            let input = self.due_inputs[i];
+
+            if !self.is_input_owner(&input) {
+                continue;
+            }
+
+            self.inputs.mark_processed(&input);
+
+            match input.command {
+                Command::Shoot(shooter, dirx, diry) => {
+                    let shooter_id = shooter.index;
+
+                    // the delay is checked at the tick the shot applies to
+                    // no room for the bullet, the shot does not happen
+                    if self.spheres.is_full() {
+                        continue;
+                    }
+
+                    {
+                        let shooter = self.spheres.at_mut(shooter_id);
+
+                        if shooter.shoot_delay > 0.0 {
+                            continue;
+                        }
+
+                        shooter.reset_shoot_delay();
+                    }
+
+                    let (force, base_vx, base_vy) =
+                        World::get_shoot_force(&self.config, self.spheres.at(shooter_id));
 
-            match *command {
-                Command::Shoot(shooter_id, dirx, diry) => {
+                    // the bullet takes its part of the shooter before the shooter shrinks,
+                    // so the two together keep the mass (and the momentum) of the shooter
                     {
-                        let shooter = self.spheres.get_mut(shooter_id);
-                        shooter.shoot(dirx, diry);
+                        self.spawn_bullet(shooter_id, dirx, diry, base_vx, base_vy);
                     }
 
                     {
-                        self.spawn_bullet(shooter_id, dirx, diry);
+                        let shooter = self.spheres.at_mut(shooter_id);
+                        shooter.shoot(dirx, diry, force, self.config.physics);
                     }
                 }
+                Command::Move(sphere, target_x, target_y) => {
+                    self.spheres.at_mut(sphere.index).move_target = Some((target_x, target_y));
+                }
             }
         }
-        self.commands.clear();
+
+        // the quad tree and sphere_ids still hold ids from the last update, so slots
+        // are only released right before they are rebuilt
+        if self.shrink_requested {
+            self.spheres.shrink_to_fit();
+            self.shrink_requested = false;
+        }
 
         // updates
-        self.qt.clear();
+        self.place_spheres();
+
+        self.move_spheres(dt);
+
+        self.sectors.clear_trees();
+
+        for id in self.awake_ids.iter() {
+            let sphere = &self.spheres.objs[*id];
 
-        self.spheres.get_alive_ids(&mut self.sphere_ids);
+            self.sectors.add_reach(sphere.x, sphere.y, sphere.r);
+        }
 
         for id in self.sphere_ids.iter() {
-            let sphere = &mut self.spheres.objs[*id];
+            let sphere = &self.spheres.objs[*id];
 
-            sphere.update(dt);
-            self.qt.insert(
-                *id,
-                sphere.x - sphere.r,
-                sphere.y - sphere.r,
-                sphere.r * 2.0,
-                sphere.r * 2.0,
-            );
+            self.sectors
+                .insert(*id, sphere.x, sphere.y, sphere.r, self.config.topology);
         }
 
         // check collisions
         self.check_collision();
+        self.handle_virus_bursts();
 
         // update high scores
-        self.highscore_player_ids.clear();
+        self.leaderboard.clear();
 
         for id in self.sphere_ids.iter() {
-            let s1 = &self.spheres.objs[*id];
-
-            if s1.r#type != SphereType::PLAYER {
-                continue;
-            }
+            let sphere = &mut self.spheres.objs[*id];
 
-            if self.highscore_player_ids.len() == 0 {
-                self.highscore_player_ids.push(*id);
-                continue;
-            }
+            if sphere.r#type == SphereType::PLAYER {
+                sphere.time_alive += dt;
+                sphere.peak_score = usize::max(sphere.peak_score, sphere.get_score());
 
-            let mut start = 0;
-            let mut end = (self.highscore_player_ids.len() - 1) as i32;
+                if sphere.was_touching_am && !sphere.touching_am && sphere.r > 0.0 {
+                    self.events.push(WorldEvent::AmEscape {
+                        uid: sphere.uid,
+                        r: sphere.r,
+                    });
+                }
 
-            while start <= end {
-                let mid = (start + end) / 2;
-                let s2 = &self.spheres.get(self.highscore_player_ids[mid as usize]);
+                sphere.was_touching_am = sphere.touching_am;
+                sphere.touching_am = false;
 
-                if s1.r > s2.r {
-                    end = mid - 1
-                } else {
-                    start = mid + 1;
+                if !sphere.is_fake && sphere.r > 0.0 {
+                    self.events.push(WorldEvent::Stats {
+                        uid: sphere.uid,
+                        score: sphere.get_score(),
+                        time_alive: sphere.time_alive,
+                        r: sphere.r,
+                    });
                 }
-            }
 
-            self.highscore_player_ids.insert(start as usize, *id);
+                self.leaderboard.push(*id, sphere.uid, sphere.r);
+            }
         }
 
-        for i in 0..self.highscore_player_ids.len() {
-            let sphere = self.spheres.get_mut(self.highscore_player_ids[i]);
+        self.leaderboard.sort();
+
+        for i in 0..self.leaderboard.len() {
+            let sphere = self.spheres.at_mut(self.leaderboard.get_ids()[i]);
             sphere.rank = i + 1;
         }
 
         // free spheres, or respawn fake player
         for i in 0..self.sphere_ids.len() {
             let id = self.sphere_ids[i];
+
+            if let Some(shooter_id) = self.get_bullet_killer(id) {
+                self.record_kill(shooter_id, id, true);
+            }
+
             let sphere = &mut self.spheres.objs[id];
 
             if sphere.r <= 0.0 {
-                if sphere.is_fake {
-                    self.respawn_fake_player(id);
+                if sphere.r#type == SphereType::PLAYER {
+                    let name = if sphere.is_fake {
+                        match sphere.name {
+                            Some(name) => String::from(FAKE_PLAYER_NAMES[name]),
+                            None => String::new(),
+                        }
+                    } else {
+                        self.player_names.remove(&sphere.uid).unwrap_or_default()
+                    };
+
+                    self.events.push(WorldEvent::Death {
+                        uid: sphere.uid,
+                        is_fake: sphere.is_fake,
+                        name,
+                        peak_score: sphere.peak_score,
+                        time_alive: sphere.time_alive,
+                        kills: sphere.kills,
+                    });
+                }
+
+                if sphere.is_fake && self.respawn_fake_player(id).is_ok() {
                     continue;
                 }
 
-                self.spheres.free(id);
+                let sphere = &self.spheres.objs[id];
+
+                if sphere.r#type == SphereType::PLAYER {
+                    self.inputs.forget(sphere.uid);
+                }
+
+                let handle = self.spheres.handle(id);
+                self.spheres.free(handle);
+            }
+        }
+
+        // what is left is placed again by the next update
+        self.sphere_ids.retain(|id| self.spheres.is_alive(*id));
+
+        self.tick += 1;
+    }
+
+    // integrate the sphere and keep it inside the world, obstacles are not included.
+    // client side prediction uses this too, so it moves spheres the same way
+    pub fn move_sphere(config: &WorldConfig, size: f64, sphere: &mut Sphere, dt: f64) {
+        let motion = config.get_sphere_motion_profile(sphere.r#type, sphere.is_fake);
+
+        if config.control == ControlScheme::MOVE {
+            sphere.steer(dt, motion, config.topology, size);
+        }
+
+        // the top speed would eat the momentum of a recoil
+        sphere.update(dt, motion, config.physics != PhysicsModel::MOMENTUM);
+
+        match config.topology {
+            Topology::BOUNDED => sphere.bounce(size),
+            Topology::TOROIDAL => sphere.wrap(size),
+            Topology::CIRCULAR => {
+                let radius = size * 0.5;
+
+                sphere.bounce_circle(radius, radius, radius)
+            }
+        }
+    }
+
+    // push the sphere out of the obstacles it overlaps, client side prediction uses this too
+    pub fn bounce_off_obstacles(obstacles: &[Obstacle], sphere: &mut Sphere) {
+        for obstacle in obstacles.iter() {
+            if let Some((nx, ny, depth)) = obstacle.get_contact(sphere.x, sphere.y, sphere.r) {
+                sphere.bounce_off(nx, ny, depth);
+            }
+        }
+    }
+
+    // move every awake sphere and push it out of the obstacles. Every sphere moves on its
+    // own, so the awake spheres are split over the threads when there is more than one chunk
+    fn move_spheres(&mut self, dt: f64) {
+        let chunk_len = get_chunk_len(self.awake_ids.len(), self.threads);
+
+        if chunk_len >= self.awake_ids.len() {
+            for id in self.awake_ids.iter() {
+                let sphere = self.spheres.at_mut(*id);
+
+                World::move_sphere(&self.config, self.size, sphere, dt);
+
+                World::bounce_off_obstacles(&self.obstacles, sphere);
+            }
+
+            return;
+        }
+
+        self.awake_mask.clear();
+        self.awake_mask.resize(self.spheres.objs.len(), false);
+
+        for id in self.awake_ids.iter() {
+            self.awake_mask[*id] = true;
+        }
+
+        let mut spheres: Vec<&mut Sphere> = self
+            .spheres
+            .objs
+            .iter_mut()
+            .zip(self.awake_mask.iter())
+            .filter_map(|(sphere, awake)| if *awake { Some(sphere) } else { None })
+            .collect();
+
+        let (config, size, obstacles) = (&self.config, self.size, &self.obstacles);
+
+        parallel::for_each(spheres.chunks_mut(chunk_len).collect(), |chunk| {
+            for sphere in chunk.iter_mut() {
+                World::move_sphere(config, size, sphere, dt);
+
+                World::bounce_off_obstacles(obstacles, sphere);
+            }
+        });
+    }
+
+    // split the world into sectors again when its size changed,
+    // every sphere is then placed again by the next update
+    fn resize_sectors(&mut self) {
+        if self.sectors.resize(self.size) {
+            self.sphere_ids.clear();
+            self.spheres.get_alive_ids(&mut self.spawned_ids);
+        }
+
+        self.spheres.set_max_size(Some(self.get_max_spheres()));
+    }
+
+    // put the spheres of the last update and the new ones in their sectors,
+    // then fill sphere_ids and awake_ids
+    fn place_spheres(&mut self) {
+        self.resize_sectors();
+        self.sectors.clear_indexed();
+        self.sphere_ids.extend_from_slice(&self.spawned_ids);
+        self.spawned_ids.clear();
+        self.player_positions.clear();
+
+        for id in self.sphere_ids.iter() {
+            let sphere = self.spheres.at(*id);
+
+            self.sectors.place(*id, sphere.x, sphere.y);
+
+            if sphere.r#type == SphereType::PLAYER && !sphere.is_fake {
+                self.player_positions.push((sphere.x, sphere.y));
+            }
+        }
+
+        self.sectors.update(
+            self.config.topology,
+            self.config.sleep,
+            &self.player_positions,
+        );
+        self.sectors.get_ids(true, &mut self.sphere_ids);
+        self.sectors.get_ids(false, &mut self.awake_ids);
+    }
+
+    // returns (force, bullet base vx, bullet base vy) for a shot of the shooter
+    pub fn get_shoot_force(config: &WorldConfig, shooter: &Sphere) -> (f64, f64, f64) {
+        match config.physics {
+            PhysicsModel::CLASSIC => (SHOOT_FORCE, 0.0, 0.0),
+            PhysicsModel::MOMENTUM => {
+                // the bullet leaves with the shooter velocity plus BULLET_SPEED, so the
+                // shooter recoils by BULLET_SPEED * bullet mass / mass left after the shot
+                (
+                    BULLET_SPEED * BULLET_AREA_RATIO * BULLET_AREA_RATIO / SHOOT_AREA_RATIO_SQ,
+                    shooter.vx,
+                    shooter.vy,
+                )
+            }
+        }
+    }
+
+    fn update_size(&mut self, dt: f64) {
+        let players = self.leaderboard.len() as f64;
+        let target_size = f64::clamp(
+            f64::sqrt(players * WORLD_AREA_PER_PLAYER),
+            WORLD_SIZE_MIN,
+            WORLD_SIZE_MAX,
+        );
+
+        let step = WORLD_RESIZE_SPEED * dt;
+        let new_size = self.size + f64::clamp(target_size - self.size, -step, step);
+
+        if new_size == self.size {
+            return;
+        }
+
+        // the walls push the moving spheres inside when the world shrinks (they move at most
+        // `step` per frame), but a toroidal world has no walls, so scale the positions instead
+        if self.config.topology == Topology::TOROIDAL {
+            let ratio = new_size / self.size;
+
+            // also the spheres in sleeping sectors
+            for i in 0..self.spheres.get_alive().len() {
+                let sphere = self.spheres.at_mut(self.spheres.get_alive()[i]);
+
+                sphere.x *= ratio;
+                sphere.y *= ratio;
+            }
+        } else if self.config.sleep && new_size < self.size {
+            // the spheres in sleeping sectors do not move, so push them inside here
+            for i in 0..self.spheres.get_alive().len() {
+                let sphere = self.spheres.at_mut(self.spheres.get_alive()[i]);
+
+                match self.config.topology {
+                    Topology::CIRCULAR => {
+                        let radius = new_size * 0.5;
+
+                        sphere.bounce_circle(radius, radius, radius);
+                    }
+                    _ => sphere.bounce(new_size),
+                }
+            }
+        }
+
+        self.size = new_size;
+    }
+
+    // signed distance from a to b along one axis, taking the topology into account
+    pub fn delta(&self, a: f64, b: f64) -> f64 {
+        match self.config.topology {
+            Topology::BOUNDED | Topology::CIRCULAR => b - a,
+            Topology::TOROIDAL => wrap_delta(a, b, self.size),
+        }
+    }
+
+    // random position where a sphere of radius r fits inside the world,
+    // uniformly distributed over the square or the disc
+    fn rand_position(&self, r: f64) -> (f64, f64) {
+        match self.config.topology {
+            Topology::BOUNDED | Topology::TOROIDAL => {
+                (rand(r, self.size - r), rand(r, self.size - r))
+            }
+            Topology::CIRCULAR => {
+                let radius = self.size * 0.5;
+                let distance = (radius - r) * f64::sqrt(rand(0.0, 1.0));
+                let angle = rand(0.0, std::f64::consts::PI * 2.0);
+
+                (
+                    radius + distance * f64::cos(angle),
+                    radius + distance * f64::sin(angle),
+                )
+            }
+        }
+    }
+
+    // fill qt_check_ids with the ids of spheres that may overlap the region
+    fn query_region(&mut self, x: f64, y: f64, w: f64, h: f64) {
+        let mut ids = std::mem::take(&mut self.qt_check_ids);
+
+        self.get_ids_in_region(x, y, w, h, &mut ids);
+        self.qt_check_ids = ids;
+    }
+
+    // ids of spheres that may overlap the region, based on the quad trees from the last update
+    // and the sleeping sectors. the same id can be returned more than once
+    pub fn get_ids_in_region(&self, x: f64, y: f64, w: f64, h: f64, out: &mut Vec<usize>) {
+        self.find_ids_in_region(x, y, w, h, true, out);
+    }
+
+    // sleeping: also look in the sleeping sectors. collisions do not need to, an awake
+    // sphere can not reach past the indexed sectors around it
+    fn find_ids_in_region(
+        &self,
+        x: f64,
+        y: f64,
+        w: f64,
+        h: f64,
+        sleeping: bool,
+        out: &mut Vec<usize>,
+    ) {
+        out.clear();
+
+        match self.config.topology {
+            Topology::BOUNDED | Topology::CIRCULAR => {
+                self.sectors.add_ids_in_region(x, y, w, h, sleeping, out);
+            }
+            Topology::TOROIDAL => {
+                for ox in [-self.size, 0.0, self.size] {
+                    for oy in [-self.size, 0.0, self.size] {
+                        self.sectors
+                            .add_ids_in_region(x + ox, y + oy, w, h, sleeping, out);
+                    }
+                }
             }
         }
     }
 
+    pub fn add_obstacle(&mut self, obstacle: Obstacle) {
+        self.obstacles.push(obstacle);
+    }
+
     fn check_spawn_collision(&mut self, x: f64, y: f64, r: f64) -> bool {
-        self.qt
-            .get_data_in_region(x - r, y - r, r * 2.0, r * 2.0, &mut self.qt_check_ids);
+        for obstacle in self.obstacles.iter() {
+            if obstacle.get_contact(x, y, r).is_some() {
+                return true;
+            }
+        }
+
+        self.query_region(x - r, y - r, r * 2.0, r * 2.0);
 
         for i in 0..self.qt_check_ids.len() {
             let id = self.qt_check_ids[i];
             let s = &self.spheres.objs[id];
 
-            let distance_sq = (x - s.x) * (x - s.x) + (y - s.y) * (y - s.y);
+            let dx = self.delta(x, s.x);
+            let dy = self.delta(y, s.y);
+            let distance_sq = dx * dx + dy * dy;
             let r_total_sq = (s.r + r) * (s.r + r);
 
             if distance_sq <= r_total_sq {
@@ -197,18 +675,34 @@ impl World {
         return false;
     }
 
+    // a free spot for a sphere of radius r, none after MAX_SPAWN_ATTEMPTS tries
+    fn find_spawn_position(&mut self, r: f64) -> Option<(f64, f64)> {
+        for _ in 0..MAX_SPAWN_ATTEMPTS {
+            let (x, y) = self.rand_position(r);
+
+            if !self.check_spawn_collision(x, y, r) {
+                return Some((x, y));
+            }
+        }
+
+        return None;
+    }
+
     pub fn spawn_food(&mut self) {
         let r = rand(FOOD_SPAWN_R_MIN, FOOD_SPAWN_R_MAX);
-        let x = rand(r, WORLD_SIZE - r);
-        let y = rand(r, WORLD_SIZE - r);
+        let (x, y) = self.rand_position(r);
 
-        if self.check_spawn_collision(x, y, r) {
+        if !self.sectors.has_budget(x, y) || self.check_spawn_collision(x, y, r) {
             return;
         }
 
         let color = rand_color(SPHERE_COLOR_MIN, SPHERE_COLOR_MAX);
 
-        let (_, sphere) = self.spheres.obtain();
+        let (handle, sphere) = match self.spheres.obtain() {
+            Ok(obtained) => obtained,
+            Err(_) => return,
+        };
+        self.spawned_ids.push(handle.index);
 
         sphere.set(
             x,
@@ -223,18 +717,22 @@ impl World {
             false,
         );
         self.increment_uid();
+        self.sectors.add(x, y);
     }
 
     pub fn spawn_am(&mut self) {
         let r = rand(AM_SPAWN_R_MIN, AM_SPAWN_R_MAX);
-        let x = rand(r, WORLD_SIZE - r);
-        let y = rand(r, WORLD_SIZE - r);
+        let (x, y) = self.rand_position(r);
 
-        if self.check_spawn_collision(x, y, r) {
+        if !self.sectors.has_budget(x, y) || self.check_spawn_collision(x, y, r) {
             return;
         }
 
-        let (_, sphere) = self.spheres.obtain();
+        let (handle, sphere) = match self.spheres.obtain() {
+            Ok(obtained) => obtained,
+            Err(_) => return,
+        };
+        self.spawned_ids.push(handle.index);
         sphere.set(
             x,
             y,
@@ -248,25 +746,45 @@ impl World {
             false,
         );
         self.increment_uid();
+        self.sectors.add(x, y);
     }
 
-    pub fn spawn_player(&mut self, _name: String) -> (usize, usize) {
-        let mut x: f64;
-        let mut y: f64;
+    pub fn spawn_virus(&mut self) {
+        let r = rand(VIRUS_SPAWN_R_MIN, VIRUS_SPAWN_R_MAX);
+        let (x, y) = self.rand_position(r);
+
+        if !self.sectors.has_budget(x, y) || self.check_spawn_collision(x, y, r) {
+            return;
+        }
 
+        let (handle, sphere) = match self.spheres.obtain() {
+            Ok(obtained) => obtained,
+            Err(_) => return,
+        };
+        self.spawned_ids.push(handle.index);
+        sphere.set(
+            x,
+            y,
+            0.0,
+            0.0,
+            r,
+            VIRUS_COLOR,
+            SphereType::VIRUS,
+            self.current_uid,
+            None,
+            false,
+        );
+        self.increment_uid();
+        self.sectors.add(x, y);
+    }
+
+    pub fn spawn_player(&mut self, name: String) -> Result<Handle, SpawnError> {
         let r = rand(
             STARTING_PLAYER_R - STARTING_PLAYER_R_RANDOMNESS,
             STARTING_PLAYER_R + STARTING_PLAYER_R_RANDOMNESS,
         );
 
-        loop {
-            x = rand(r, WORLD_SIZE - r);
-            y = rand(r, WORLD_SIZE - r);
-
-            if !self.check_spawn_collision(x, y, r) {
-                break;
-            }
-        }
+        let (x, y) = self.find_spawn_position(r).ok_or(SpawnError::NOROOM)?;
 
         let color = rand_color(SPHERE_COLOR_MIN, SPHERE_COLOR_MAX);
 
@@ -280,32 +798,24 @@ impl World {
         let vx = dirx * speed * sx;
         let vy = diry * speed * sy;
 
-        let (id, sphere) = self.spheres.obtain();
+        let (id, sphere) = self.spheres.obtain()?;
+        self.spawned_ids.push(id.index);
         let uid = self.current_uid;
         sphere.set(x, y, vx, vy, r, color, SphereType::PLAYER, uid, None, false);
+        self.player_names.insert(uid, name);
 
         self.increment_uid();
 
-        return (id, uid);
+        return Ok(id);
     }
 
-    pub fn spawn_fake_player(&mut self) -> usize {
-        let mut x: f64;
-        let mut y: f64;
-
+    pub fn spawn_fake_player(&mut self) -> Result<Handle, SpawnError> {
         let r = rand(
             STARTING_PLAYER_R - STARTING_PLAYER_R_RANDOMNESS,
             STARTING_PLAYER_R + STARTING_PLAYER_R_RANDOMNESS,
         );
 
-        loop {
-            x = rand(r, WORLD_SIZE - r);
-            y = rand(r, WORLD_SIZE - r);
-
-            if !self.check_spawn_collision(x, y, r) {
-                break;
-            }
-        }
+        let (x, y) = self.find_spawn_position(r).ok_or(SpawnError::NOROOM)?;
 
         let color = rand_color(SPHERE_COLOR_MIN, SPHERE_COLOR_MAX);
 
@@ -319,7 +829,8 @@ impl World {
         let vx = dirx * speed * sx;
         let vy = diry * speed * sy;
 
-        let (id, sphere) = self.spheres.obtain();
+        let (id, sphere) = self.spheres.obtain()?;
+        self.spawned_ids.push(id.index);
         sphere.set(
             x,
             y,
@@ -334,29 +845,24 @@ impl World {
         );
         self.increment_uid();
 
-        return id;
+        return Ok(id);
     }
 
-    pub fn respawn_fake_player(&mut self, id: usize) {
-        let mut x: f64;
-        let mut y: f64;
-
+    // fails if there is no room left, the caller frees the sphere then
+    pub fn respawn_fake_player(&mut self, id: usize) -> Result<(), SpawnError> {
         let r = rand(
             STARTING_PLAYER_R - STARTING_PLAYER_R_RANDOMNESS,
             STARTING_PLAYER_R + STARTING_PLAYER_R_RANDOMNESS,
         );
 
-        loop {
-            x = rand(r, WORLD_SIZE - r);
-            y = rand(r, WORLD_SIZE - r);
+        let (x, y) = self.find_spawn_position(r).ok_or(SpawnError::NOROOM)?;
 
-            if !self.check_spawn_collision(x, y, r) {
-                break;
-            }
-        }
+        // a new life, handles to the old one (e.g. as a shooter) stop resolving
+        self.spheres.renew(id);
 
-        let prev = self.spheres.get_mut(id);
+        let prev = self.spheres.at_mut(id);
 
+        prev.reset_stats();
         prev.r = r;
         prev.x = x;
         prev.y = y;
@@ -375,19 +881,33 @@ impl World {
         if rand_int(0, 100) >= 90 {
             prev.name = Some(rand_int(0, FAKE_NAME_LEN as i32) as usize);
         }
+
+        return Ok(());
     }
 
-    pub fn spawn_bullet(&mut self, shooter_id: usize, dirx: f64, diry: f64) {
-        let shooter = self.spheres.get(shooter_id);
+    pub fn spawn_bullet(
+        &mut self,
+        shooter_id: usize,
+        dirx: f64,
+        diry: f64,
+        base_vx: f64,
+        base_vy: f64,
+    ) {
+        let shooter = self.spheres.at(shooter_id);
 
         let r = shooter.r * BULLET_AREA_RATIO;
         let x = shooter.x + dirx * (shooter.r - r);
         let y = shooter.y + diry * (shooter.r - r);
-        let vx = dirx * BULLET_SPEED;
-        let vy = diry * BULLET_SPEED;
+        let vx = base_vx + dirx * BULLET_SPEED;
+        let vy = base_vy + diry * BULLET_SPEED;
         let color = darken_color(shooter.color, 0.75);
+        let shooter_handle = self.spheres.handle(shooter_id);
 
-        let (_, sphere) = self.spheres.obtain();
+        let (handle, sphere) = match self.spheres.obtain() {
+            Ok(obtained) => obtained,
+            Err(_) => return,
+        };
+        self.spawned_ids.push(handle.index);
         sphere.set(
             x,
             y,
@@ -400,79 +920,280 @@ impl World {
             None,
             false,
         );
-        sphere.set_shooter(shooter_id);
+        sphere.set_shooter(shooter_handle);
 
         self.increment_uid();
     }
 
-    pub fn shoot(&mut self, id: usize, x: f64, y: f64) {
-        let sphere = self.spheres.get_mut(id);
+    // (x, y) is the direction, a zero or non finite one is ignored
+    pub fn shoot(&mut self, sphere: Handle, x: f64, y: f64) {
+        let (dirx, diry) = match get_direction(x, y) {
+            Some(direction) => direction,
+            None => return,
+        };
+
+        self.submit_local_command(sphere, Command::Shoot(sphere, dirx, diry));
+    }
 
-        if sphere.shoot_delay > 0.0 {
+    // steer the sphere toward (x, y) in world coordinates, ignored unless the world uses
+    // ControlScheme::MOVE
+    pub fn move_to(&mut self, sphere: Handle, x: f64, y: f64) {
+        if self.config.control != ControlScheme::MOVE || !x.is_finite() || !y.is_finite() {
             return;
+        }
+
+        self.submit_local_command(sphere, Command::Move(sphere, x, y));
+    }
+
+    // queue a command from a player sitting next to the world, it applies on the next update
+    fn submit_local_command(&mut self, sphere: Handle, command: Command) {
+        let uid = match self.spheres.get(sphere) {
+            Some(sphere) => sphere.uid,
+            None => return,
         };
 
-        sphere.reset_shoot_delay();
+        let seq = self.inputs.next_seq(uid);
 
-        let len = f64::sqrt(x * x + y * y);
-        let dirx = x / len;
-        let diry = y / len;
+        let _ = self.submit_input(Input {
+            uid,
+            seq,
+            tick: self.tick,
+            command,
+        });
+    }
 
-        let command = Command::Shoot(id, dirx, diry);
-        self.commands.push(command);
+    // queue a sequenced input, the command applies on the update that processes input.tick
+    pub fn submit_input(&mut self, input: Input) -> Result<(), InputError> {
+        if !input.command.is_valid() {
+            return Err(InputError::INVALID);
+        }
+
+        if !self.is_input_owner(&input) {
+            return Err(InputError::UNOWNED);
+        }
+
+        self.inputs.push(input, self.tick)
+    }
+
+    // is the sphere the command is for still the live player that sent it
+    fn is_input_owner(&self, input: &Input) -> bool {
+        let handle = match input.command {
+            Command::Shoot(handle, _, _) => handle,
+            Command::Move(handle, _, _) => handle,
+        };
+
+        let sphere = match self.spheres.get(handle) {
+            Some(sphere) => sphere,
+            None => return false,
+        };
+
+        return sphere.uid == input.uid && sphere.r > 0.0 && sphere.r#type == SphereType::PLAYER;
     }
 
+    // the quad tree queries run in parallel, the collisions are then resolved one pair
+    // at a time in the order of awake_ids, like a single thread would
     fn check_collision(&mut self) {
-        let n = self.sphere_ids.len();
-
-        for i in 0..(n - 1) {
-            let id1 = self.sphere_ids[i];
-            let s1 = &self.spheres.objs[id1];
-
-            self.qt.get_data_in_region(
-                s1.x - s1.r,
-                s1.y - s1.r,
-                s1.r * 2.0,
-                s1.r * 2.0,
-                &mut self.qt_check_ids,
-            );
+        self.find_collision_candidates();
+
+        let candidates = std::mem::take(&mut self.collision_candidates);
 
-            // log(&format!("{}", other_ids.len()));
+        for pairs in candidates.iter() {
+            for (id1, id2) in pairs.iter() {
+                self.resolve_collision(*id1, *id2);
+            }
+        }
 
-            for j in self.qt_check_ids.iter() {
-                let id2 = *j;
+        self.collision_candidates = candidates;
+    }
 
-                let s1 = &self.spheres.objs[id1];
-                let s2 = &self.spheres.objs[id2];
+    // fill collision_candidates with the quad tree results of every awake sphere
+    fn find_collision_candidates(&mut self) {
+        let n = self.awake_ids.len();
+        let chunk_len = get_chunk_len(n, self.threads);
+        let chunk_count = n.div_ceil(chunk_len);
 
-                if id1 == id2 {
-                    continue;
-                }
+        let mut candidates = std::mem::take(&mut self.collision_candidates);
+        let mut queries = std::mem::take(&mut self.candidate_queries);
+        candidates.resize_with(chunk_count, Vec::new);
+        queries.resize_with(chunk_count, Vec::new);
 
-                if s1.shooter_id == Some(id2) || s2.shooter_id == Some(id1) {
-                    continue;
-                }
+        let world = &*self;
+        let chunks: Vec<_> = candidates
+            .iter_mut()
+            .zip(queries.iter_mut())
+            .enumerate()
+            .collect();
 
-                let distance_sq = (s2.x - s1.x) * (s2.x - s1.x) + (s2.y - s1.y) * (s2.y - s1.y);
-                let r_total_sq = (s1.r + s2.r) * (s1.r + s2.r);
+        parallel::for_each(chunks, |(chunk, (pairs, query))| {
+            pairs.clear();
 
-                if distance_sq <= r_total_sq {
-                    let (r1, r2) = World::handle_collision(s1, s2, distance_sq);
+            for i in (chunk * chunk_len)..usize::min((chunk + 1) * chunk_len, n) {
+                let id1 = world.awake_ids[i];
+                let s1 = world.spheres.at(id1);
 
-                    {
-                        let s1 = &mut self.spheres.objs[id1];
+                let (x, y, size) = (s1.x - s1.r, s1.y - s1.r, s1.r * 2.0);
+
+                world.find_ids_in_region(x, y, size, size, false, query);
 
-                        s1.r = r1;
+                for id2 in query.iter() {
+                    if *id2 != id1 {
+                        pairs.push((id1, *id2));
                     }
+                }
+            }
+        });
 
-                    {
-                        let s2 = &mut self.spheres.objs[id2];
+        self.collision_candidates = candidates;
+        self.candidate_queries = queries;
+    }
+
+    fn resolve_collision(&mut self, id1: usize, id2: usize) {
+        let s1 = &self.spheres.objs[id1];
+        let s2 = &self.spheres.objs[id2];
+
+        if s1.shooter_id == Some(self.spheres.handle(id2))
+            || s2.shooter_id == Some(self.spheres.handle(id1))
+        {
+            return;
+        }
+
+        let dx = self.delta(s1.x, s2.x);
+        let dy = self.delta(s1.y, s2.y);
+        let distance_sq = dx * dx + dy * dy;
+        let r_total_sq = (s1.r + s2.r) * (s1.r + s2.r);
+
+        if distance_sq > r_total_sq {
+            return;
+        }
+
+        if let Some(burst) = World::get_virus_burst(id1, s1, id2, s2) {
+            self.virus_bursts.push(burst);
+            return;
+        }
+
+        let (r1, r2) = World::handle_collision(s1, s2, distance_sq);
+
+        if World::is_notable_collision(s1, s2) && (r1 != s1.r || r2 != s2.r) {
+            self.collision_points
+                .push((s1.x + dx * 0.5, s1.y + dy * 0.5));
+        }
+
+        let is_player_collision =
+            s1.r#type == SphereType::PLAYER && s2.r#type == SphereType::PLAYER;
+        let (old_r1, old_r2) = (s1.r, s2.r);
+        let conserve_momentum = self.config.physics == PhysicsModel::MOMENTUM;
+        let (vx1, vy1, vx2, vy2) = (s1.vx, s1.vy, s2.vx, s2.vy);
+
+        {
+            let s1 = &mut self.spheres.objs[id1];
+
+            if conserve_momentum {
+                s1.gain_momentum(r1, vx2, vy2);
+            }
+
+            s1.r = r1;
+        }
+
+        {
+            let s2 = &mut self.spheres.objs[id2];
+
+            if conserve_momentum {
+                s2.gain_momentum(r2, vx1, vy1);
+            }
+
+            s2.r = r2;
+        }
+
+        if is_player_collision && old_r1 > 0.0 && r1 <= 0.0 {
+            self.record_kill(id2, id1, false);
+        }
+
+        if is_player_collision && old_r2 > 0.0 && r2 <= 0.0 {
+            self.record_kill(id1, id2, false);
+        }
+
+        self.mark_contact(id1, id2);
+        self.mark_contact(id2, id1);
+    }
+
+    // collisions worth watching, eating food and bullets is not
+    fn is_notable_collision(s1: &Sphere, s2: &Sphere) -> bool {
+        let is_notable = |s: &Sphere| s.r#type == SphereType::AM || s.r#type == SphereType::VIRUS;
 
-                        s2.r = r2;
+        return (s1.r#type == SphereType::PLAYER && s2.r#type == SphereType::PLAYER)
+            || is_notable(s1)
+            || is_notable(s2);
+    }
+
+    fn record_kill(&mut self, killer_id: usize, victim_id: usize, by_bullet: bool) {
+        let victim = self.spheres.at_mut(victim_id);
+        let victim_uid = victim.uid;
+
+        // the bullet hit can not be credited again
+        victim.last_hit_by = None;
+
+        let killer = self.spheres.at_mut(killer_id);
+
+        killer.kills += 1;
+
+        self.events.push(WorldEvent::Kill {
+            killer_uid: killer.uid,
+            victim_uid,
+            killer_r: killer.r,
+            by_bullet,
+        });
+    }
+
+    // remember what the player sphere touched, for kill credit and achievements
+    fn mark_contact(&mut self, id: usize, other_id: usize) {
+        let other = self.spheres.at(other_id);
+        let other_type = other.r#type;
+        // the shooter may be dead and its slot reused
+        let shooter = match other.shooter_id {
+            Some(shooter_id) if other_type == SphereType::BULLET => {
+                match self.spheres.get(shooter_id) {
+                    Some(shooter)
+                        if shooter.r#type == SphereType::PLAYER && shooter_id.index != id =>
+                    {
+                        Some(shooter_id)
                     }
+                    _ => None,
                 }
             }
+            _ => None,
+        };
+
+        let sphere = self.spheres.at_mut(id);
+
+        if sphere.r#type != SphereType::PLAYER || sphere.r <= 0.0 {
+            return;
+        }
+
+        if other_type == SphereType::AM {
+            sphere.touching_am = true;
+        }
+
+        if let Some(shooter_id) = shooter {
+            sphere.set_last_hit(shooter_id);
+        }
+    }
+
+    // the shooter to credit if the player sphere died shortly after a bullet hit it
+    fn get_bullet_killer(&self, id: usize) -> Option<usize> {
+        let sphere = self.spheres.at(id);
+
+        if sphere.r > 0.0 || sphere.r#type != SphereType::PLAYER || sphere.last_hit_time <= 0.0 {
+            return None;
+        }
+
+        let shooter_id = sphere.last_hit_by?;
+
+        // the shooter may be dead and its slot reused
+        if !self.spheres.is_valid(shooter_id) {
+            return None;
         }
+
+        return Some(shooter_id.index);
     }
 
     fn handle_collision(s1: &Sphere, s2: &Sphere, distance_sq: f64) -> (f64, f64) {
@@ -489,6 +1210,9 @@ impl World {
 
         if s1.r#type == SphereType::AM || s2.r#type == SphereType::AM {
             Sphere::melt(s1, s2, bigger, distance_sq)
+        } else if s1.r#type == SphereType::VIRUS || s2.r#type == SphereType::VIRUS {
+            // small spheres (and everything that is not a player) pass through viruses
+            (s1.r, s2.r)
         } else if (bigger.r#type == SphereType::FOOD || bigger.r#type == SphereType::BULLET)
             && smaller.r#type != SphereType::FOOD
             && smaller.r#type != SphereType::BULLET
@@ -498,4 +1222,266 @@ impl World {
             Sphere::absorb(s1, s2, bigger, distance_sq)
         }
     }
+
+    // returns (victim id, virus id) if the collision should burst the player sphere
+    fn get_virus_burst(id1: usize, s1: &Sphere, id2: usize, s2: &Sphere) -> Option<(usize, usize)> {
+        if s1.r#type == SphereType::VIRUS
+            && s2.r#type == SphereType::PLAYER
+            && s2.r > VIRUS_BURST_MIN_R
+        {
+            return Some((id2, id1));
+        }
+
+        if s2.r#type == SphereType::VIRUS
+            && s1.r#type == SphereType::PLAYER
+            && s1.r > VIRUS_BURST_MIN_R
+        {
+            return Some((id1, id2));
+        }
+
+        return None;
+    }
+
+    fn handle_virus_bursts(&mut self) {
+        for i in 0..self.virus_bursts.len() {
+            let (victim_id, virus_id) = self.virus_bursts[i];
+
+            // the virus may already be used up by another sphere this frame
+            if self.spheres.at(virus_id).r <= 0.0 {
+                continue;
+            }
+
+            if self.spheres.at(victim_id).r <= VIRUS_BURST_MIN_R {
+                continue;
+            }
+
+            self.spheres.at_mut(virus_id).r = 0.0;
+            self.burst(victim_id);
+
+            let victim = self.spheres.at(victim_id);
+            self.collision_points.push((victim.x, victim.y));
+        }
+
+        self.virus_bursts.clear();
+    }
+
+    // spray part of the sphere's mass out as food pieces flying in every direction
+    fn burst(&mut self, id: usize) {
+        let (x, y, r, color, sprayed_area) = {
+            let sphere = self.spheres.at_mut(id);
+            let sprayed_area = sphere.r * sphere.r * VIRUS_BURST_AREA_RATIO;
+
+            sphere.r = f64::sqrt(sphere.r * sphere.r - sprayed_area);
+
+            (sphere.x, sphere.y, sphere.r, sphere.color, sprayed_area)
+        };
+
+        let victim = self.spheres.handle(id);
+        let piece_r = f64::sqrt(sprayed_area / VIRUS_BURST_PIECES as f64);
+        let angle_offset = rand(0.0, std::f64::consts::PI * 2.0);
+
+        for i in 0..VIRUS_BURST_PIECES {
+            let angle =
+                angle_offset + std::f64::consts::PI * 2.0 * (i as f64) / VIRUS_BURST_PIECES as f64;
+            let dirx = f64::cos(angle);
+            let diry = f64::sin(angle);
+
+            // the rest of the sprayed mass is lost when the world is full
+            let (handle, piece) = match self.spheres.obtain() {
+                Ok(obtained) => obtained,
+                Err(_) => break,
+            };
+            self.spawned_ids.push(handle.index);
+            piece.set(
+                x + dirx * f64::max(r - piece_r, 0.0),
+                y + diry * f64::max(r - piece_r, 0.0),
+                dirx * VIRUS_BURST_SPEED,
+                diry * VIRUS_BURST_SPEED,
+                piece_r,
+                color,
+                SphereType::FOOD,
+                self.current_uid,
+                None,
+                false,
+            );
+            piece.set_shooter(victim);
+
+            self.increment_uid();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::World;
+    use crate::{
+        config::{PhysicsModel, WorldConfig},
+        constants::{
+            FOOD_SPAWN_DELAY, MAX_SPAWN_CATCH_UP, MAX_SPHERE_SPEED, R_DECREASE_RATIO, SECTOR_SIZE,
+        },
+        sphere::SphereType,
+        utils::seed_random,
+    };
+
+    // momentum of the real players and the bullets, with the area as mass
+    fn get_momentum(world: &World) -> (f64, f64) {
+        let mut momentum = (0.0, 0.0);
+
+        for id in world.spheres.get_alive() {
+            let sphere = world.spheres.at(*id);
+
+            if (sphere.r#type == SphereType::PLAYER && !sphere.is_fake)
+                || sphere.r#type == SphereType::BULLET
+            {
+                momentum.0 += sphere.r * sphere.r * sphere.vx;
+                momentum.1 += sphere.r * sphere.r * sphere.vy;
+            }
+        }
+
+        momentum
+    }
+
+    #[test]
+    fn momentum_shot_keeps_the_momentum() {
+        let dt = 1.0 / 60.0;
+
+        seed_random(42);
+
+        let mut config = WorldConfig::new();
+        config.physics = PhysicsModel::MOMENTUM;
+
+        let mut world = World::with_config(config);
+        let handle = world.spawn_player(String::from("player")).unwrap();
+
+        // close to the top speed, and firing backwards
+        {
+            let sphere = world.spheres.get_mut(handle).unwrap();
+            sphere.x = world.size * 0.5;
+            sphere.y = world.size * 0.5;
+            sphere.vx = MAX_SPHERE_SPEED - 1.0;
+            sphere.vy = 0.0;
+            sphere.shoot_delay = 0.0;
+        }
+
+        let before = get_momentum(&world);
+
+        world.shoot(handle, -1.0, 0.0);
+        world.update(dt);
+
+        let after = get_momentum(&world);
+        // every sphere loses the same part of its radius during the update
+        let decay = (1.0 - R_DECREASE_RATIO * dt) * (1.0 - R_DECREASE_RATIO * dt);
+
+        assert!(world.spheres.get(handle).unwrap().vx > MAX_SPHERE_SPEED);
+        assert!((after.0 - before.0 * decay).abs() < 1e-9 * before.0);
+        assert!(after.1.abs() < 1e-9 * before.0);
+    }
+
+    #[test]
+    fn long_update_does_not_spawn_a_burst() {
+        seed_random(42);
+
+        let mut world = World::with_config(WorldConfig::new());
+        world.init();
+
+        let before = world.spheres.len();
+
+        world.update(1e6);
+
+        // at most MAX_SPAWN_CATCH_UP seconds of food, and no am or virus
+        let spawned = (MAX_SPAWN_CATCH_UP / FOOD_SPAWN_DELAY) as usize;
+
+        assert!(world.spheres.len() <= before + spawned);
+        assert!(world.food_spawn_counter < FOOD_SPAWN_DELAY);
+    }
+
+    #[test]
+    fn sleeping_sectors_keep_their_spheres() {
+        seed_random(42);
+
+        let mut config = WorldConfig::new();
+        config.sleep = true;
+
+        let mut world = World::with_config(config);
+        world.set_size(SECTOR_SIZE * 10.0);
+        world.init();
+
+        let handle = world.spawn_player(String::from("player")).unwrap();
+
+        {
+            let player = world.spheres.get_mut(handle).unwrap();
+            player.x = SECTOR_SIZE * 0.5;
+            player.y = SECTOR_SIZE * 0.5;
+        }
+
+        // spheres far from the only real player, in the corner across the world
+        let far: Vec<_> = world
+            .spheres
+            .get_alive()
+            .iter()
+            .filter(|id| {
+                let sphere = world.spheres.at(**id);
+
+                sphere.x > world.size * 0.5 && sphere.y > world.size * 0.5
+            })
+            .map(|id| (*id, *world.spheres.at(*id)))
+            .collect();
+
+        assert!(!far.is_empty());
+
+        for _ in 0..60 {
+            world.update(1.0 / 60.0);
+        }
+
+        // the 2 by 2 sectors in the corner of the player
+        assert_eq!(world.sectors.get_awake_count(), 4);
+        assert!(!world.awake_ids.is_empty());
+
+        for id in world.awake_ids.iter() {
+            let sphere = world.spheres.at(*id);
+            let reach = SECTOR_SIZE * 2.0 + MAX_SPHERE_SPEED;
+
+            assert!(sphere.x < reach && sphere.y < reach);
+        }
+
+        for (id, sphere) in far.iter() {
+            assert!(world.spheres.at(*id) == sphere, "sphere {}", id);
+        }
+    }
+
+    // same seeded world as the other tests, moved on the given number of threads
+    #[cfg(feature = "parallel")]
+    fn run_on_threads(threads: usize) -> World {
+        seed_random(42);
+
+        let mut world = World::with_config(WorldConfig::new());
+        world.threads = threads;
+        world.init();
+        let _ = world.spawn_player(String::from("player"));
+
+        for _ in 0..300 {
+            world.update(1.0 / 60.0);
+        }
+
+        return world;
+    }
+
+    #[test]
+    #[cfg(feature = "parallel")]
+    fn thread_count_does_not_change_the_spheres() {
+        let single = run_on_threads(1);
+        let multi = run_on_threads(4);
+
+        // enough awake spheres for more than one chunk
+        assert!(single.awake_ids.len() > crate::constants::PARALLEL_MIN_CHUNK_LEN);
+        assert_eq!(single.spheres.get_alive(), multi.spheres.get_alive());
+
+        for id in single.spheres.get_alive().iter() {
+            assert!(
+                single.spheres.at(*id) == multi.spheres.at(*id),
+                "sphere {}",
+                id
+            );
+        }
+    }
 }
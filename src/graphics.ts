@@ -403,3 +403,72 @@ export function drawHighscores(
     ctx.fillText(score, x, y);
   }
 }
+
+const minimapSize = 160;
+const minimapMargin = 16;
+const minimapBgColor = "#0003";
+const minimapAmColor = "black";
+const minimapPlayerColor = "#dc2626";
+const minimapSelfColor = "white";
+// sphere types drawn as density, indexed by SphereType
+const minimapTypeColors = ["255, 255, 255", "255, 255, 255", "", "", "127, 191, 63"];
+
+// data is the buffer from Ubur.get_minimap
+export function drawMinimap(data: Float64Array, playerId: number | undefined) {
+  const resolution = data[0];
+  const typeCount = data[1];
+  const worldSize = data[2] * resolution;
+
+  const left = ctx.canvas.width - minimapSize - minimapMargin;
+  const top = ctx.canvas.height - minimapSize - minimapMargin;
+  const cellSize = minimapSize / resolution;
+  const scale = minimapSize / worldSize;
+
+  ctx.fillStyle = minimapBgColor;
+  ctx.fillRect(left, top, minimapSize, minimapSize);
+
+  let i = 3;
+
+  for (let row = 0; row < resolution; row++) {
+    for (let col = 0; col < resolution; col++) {
+      for (let t = 0; t < typeCount; t++) {
+        const density = data[i++];
+
+        if (density <= 0 || !minimapTypeColors[t]) continue;
+
+        const alpha = Math.min(density, 1) * 0.8;
+
+        ctx.fillStyle = `rgba(${minimapTypeColors[t]}, ${alpha})`;
+        ctx.fillRect(
+          left + col * cellSize,
+          top + row * cellSize,
+          cellSize,
+          cellSize
+        );
+      }
+    }
+  }
+
+  const playerCount = data[i++];
+
+  for (let p = 0; p < playerCount; p++, i += 4) {
+    const r = Math.max(data[i + 3] * scale, 2);
+
+    ctx.fillStyle = data[i] === playerId ? minimapSelfColor : minimapPlayerColor;
+    ctx.beginPath();
+    ctx.arc(left + data[i + 1] * scale, top + data[i + 2] * scale, r, 0, Math.PI * 2);
+    ctx.fill();
+  }
+
+  const amCount = data[i++];
+
+  ctx.fillStyle = minimapAmColor;
+
+  for (let a = 0; a < amCount; a++, i += 3) {
+    const r = Math.max(data[i + 2] * scale, 1);
+
+    ctx.beginPath();
+    ctx.arc(left + data[i] * scale, top + data[i + 1] * scale, r, 0, Math.PI * 2);
+    ctx.fill();
+  }
+}
@@ -1,7 +1,14 @@
-import init, { Ubur } from "../pkg/ubur";
+import init, {
+  ControlScheme,
+  SpectatorMode,
+  SphereView,
+  Topology,
+  Ubur
+} from "../pkg/ubur";
 import {
   beginDraw,
   drawHighscores,
+  drawMinimap,
   drawName,
   drawRect,
   drawScore,
@@ -17,7 +24,7 @@ import {
 import vertexShaderSource from "./simple.vert";
 import fragmentShaderSource from "./simple.frag";
 import { GameLoop } from "./gameloop";
-import { FAKE_PLAYER_NAMES } from "./utils";
+import { FAKE_PLAYER_NAMES, wrapDelta } from "./utils";
 
 const worldCanvas = document.getElementById(
   "world-canvas"
@@ -30,8 +37,9 @@ const hsButton = document.getElementById("hs-button") as HTMLButtonElement;
 
 let memory: WebAssembly.Memory;
 let ubur: Ubur;
+// the handle stays valid while the player is alive, the id is resolved from it every update
+let playerHandle: number | undefined = undefined;
 let playerId: number | undefined = undefined;
-let playerUid: number | undefined = undefined;
 let playerName = "";
 let viewX: number;
 let viewY: number;
@@ -41,19 +49,34 @@ let showHighscore = false;
 function update(dt: number) {
   ubur.update(dt);
 
-  if (!playerId || !playerUid) return;
+  const unlockedPtr = ubur.get_unlocked_achievements();
+  const unlockedLen = new Uint32Array(memory.buffer, unlockedPtr, 1)[0];
 
-  viewX = ubur.get_sphere_x(playerId);
-  viewY = ubur.get_sphere_y(playerId);
-  viewArea = ubur.get_sphere_view_area(playerId);
+  if (unlockedLen > 0) {
+    localStorage.setItem("achievements", ubur.export_achievements());
+  }
+
+  playerId =
+    playerHandle === undefined ? undefined : ubur.get_sphere_id(playerHandle);
 
-  // check is player dead
-  if (ubur.is_player_dead(playerId, playerUid)) {
-    playerId = undefined;
-    playerUid = undefined;
+  if (playerHandle === undefined) {
+    // spectate, this is also the attract mode of the title screen
+    ubur.update_spectator(dt);
+  } else if (playerId === undefined) {
+    playerHandle = undefined;
+
+    localStorage.setItem("highscores", ubur.export_highscores());
+    localStorage.setItem("achievements", ubur.export_achievements());
 
     titleUI.style.display = "flex";
   }
+
+  ubur.set_camera_screen(window.innerWidth, window.innerHeight);
+  ubur.update_camera(dt);
+
+  viewX = ubur.get_camera_x();
+  viewY = ubur.get_camera_y();
+  viewArea = ubur.get_camera_view_area();
 }
 
 const BG_CELLS_PER_ROW = 10;
@@ -66,8 +89,8 @@ const WALL_WIDTH = 4;
 const WALL_HALF_WIDTH = WALL_WIDTH * 0.5;
 
 function drawBackground() {
-  const worldSize = Ubur.world_size();
-  const bgCellSize = worldSize / BG_CELLS_PER_ROW;
+  const worldSize = ubur.get_world_size();
+  const bgCellSize = Ubur.world_size() / BG_CELLS_PER_ROW;
 
   const ar = getAspectRatio();
 
@@ -79,10 +102,18 @@ function drawBackground() {
   const top = viewY - h * 0.5;
   const bottom = viewY + h * 0.5;
 
-  const bl = Math.max(left, 0);
-  const br = Math.min(right, worldSize);
-  const bt = Math.max(top, 0);
-  const bb = Math.min(bottom, worldSize);
+  if (ubur.get_topology() === Topology.CIRCULAR) {
+    drawCircularBackground();
+    return;
+  }
+
+  // a toroidal world has no edge, so the background covers the whole view
+  const isToroidal = ubur.get_topology() === Topology.TOROIDAL;
+
+  const bl = isToroidal ? left : Math.max(left, 0);
+  const br = isToroidal ? right : Math.min(right, worldSize);
+  const bt = isToroidal ? top : Math.max(top, 0);
+  const bb = isToroidal ? bottom : Math.min(bottom, worldSize);
   const bx = (br + bl) / 2;
   const by = (bb + bt) / 2;
   const bw = br - bl;
@@ -96,7 +127,11 @@ function drawBackground() {
   // draw grid
   setColor(GRID_LINE_COLOR);
 
-  for (let i = bl - (bl % bgCellSize) + bgCellSize; i < br; i += bgCellSize) {
+  for (
+    let i = Math.floor(bl / bgCellSize) * bgCellSize + bgCellSize;
+    i < br;
+    i += bgCellSize
+  ) {
     const gx =
       i === bl
         ? i - WALL_HALF_WIDTH + GRID_LINE_WIDTH
@@ -110,7 +145,11 @@ function drawBackground() {
     drawRect(gx, by, gw, gh);
   }
 
-  for (let i = bt - (bt % bgCellSize) + bgCellSize; i < bb; i += bgCellSize) {
+  for (
+    let i = Math.floor(bt / bgCellSize) * bgCellSize + bgCellSize;
+    i < bb;
+    i += bgCellSize
+  ) {
     const gy =
       i === bt
         ? i - WALL_HALF_WIDTH + GRID_LINE_WIDTH
@@ -122,6 +161,8 @@ function drawBackground() {
     drawRect(bx, gy, bw, gh);
   }
 
+  if (isToroidal) return;
+
   // draw wall
   setColor(WALL_COLOR);
 
@@ -142,22 +183,50 @@ function drawBackground() {
   }
 }
 
-function drawPlayer() {
-  if (playerId === undefined) return;
+function drawCircularBackground() {
+  const worldRadius = ubur.get_world_radius();
+  const d = worldRadius * 2;
+
+  setCircle(true);
+
+  setColor(WALL_COLOR);
+  drawRect(worldRadius, worldRadius, d + WALL_WIDTH * 2, d + WALL_WIDTH * 2);
+
+  setColor(BG_COLOR);
+  drawRect(worldRadius, worldRadius, d, d);
+}
+
+// views are copies in wasm memory, free them once drawn
+function freeViews(views: SphereView[]) {
+  for (const view of views) {
+    view.free();
+  }
+}
+
+function drawPlayer(player: SphereView) {
+  const { r } = player;
+  let { x, y } = player;
+
+  // the player is left out of the visible loop and its offsets,
+  // so draw the copy nearest to the camera
+  if (ubur.get_topology() === Topology.TOROIDAL) {
+    const worldSize = ubur.get_world_size();
+
+    x = viewX + wrapDelta(viewX, x, worldSize);
+    y = viewY + wrapDelta(viewY, y, worldSize);
+  }
 
-  const x = viewX;
-  const y = viewY;
-  const r = ubur.get_sphere_r(playerId);
-  const color = ubur.get_sphere_color(playerId);
   const d = r * 2;
 
   setCircle(true);
-  setColor(color);
+  setColor(player.color);
   drawRect(x, y, d, d);
 
   drawName(x, y, r, playerName);
 }
 
+const MINIMAP_TOP_PLAYERS = 10;
+
 const hsScores: number[] = [];
 const hsNames: string[] = [];
 const emptyName = "-";
@@ -172,83 +241,156 @@ function draw() {
 
   drawBackground();
 
-  const ar = getAspectRatio();
-  const idsPtr = ubur.get_visible_sphere_ids(ar, viewX, viewY, viewArea);
+  const idsPtr = ubur.get_camera_visible_ids();
   const len = new Uint32Array(memory.buffer, idsPtr, 1)[0];
+  // the call can grow the wasm memory, so the arrays over it are made after
+  const views = ubur.get_sphere_views(
+    new Uint32Array(memory.buffer, idsPtr + 4, len).slice()
+  );
   const ids = new Uint32Array(memory.buffer, idsPtr + 4, len);
+  const offsets = new Float64Array(
+    memory.buffer,
+    ubur.get_visible_sphere_offsets(),
+    len * 2
+  );
 
-  for (let i = 0; i < len; i++) {
-    if (ids[i] === playerId) {
+  // views skip dead ids, so match them with the ids for the offsets
+  for (let i = 0, j = 0; i < len && j < views.length; i++) {
+    const view = views[j];
+
+    if (view.id !== ids[i]) {
       continue;
     }
 
-    const x = ubur.get_sphere_x(ids[i]);
-    const y = ubur.get_sphere_y(ids[i]);
-    const r = ubur.get_sphere_r(ids[i]);
-    const color = ubur.get_sphere_color(ids[i]);
-    const name = ubur.get_sphere_name(ids[i]);
+    j++;
 
-    if (name != undefined) {
-      drawName(x, y, r, FAKE_PLAYER_NAMES[name]);
+    if (view.id === playerId) {
+      continue;
+    }
+
+    const x = view.x + offsets[i * 2];
+    const y = view.y + offsets[i * 2 + 1];
+    const r = view.r;
+
+    if (view.name != undefined) {
+      drawName(x, y, r, FAKE_PLAYER_NAMES[view.name]);
     }
 
     const d = r * 2;
 
     setCircle(true);
-    setColor(color);
+    setColor(view.color);
     drawRect(x, y, d, d);
   }
 
-  drawPlayer();
+  freeViews(views);
+
+  const player =
+    playerId !== undefined ? ubur.get_sphere_view(playerId) : undefined;
+
+  if (player !== undefined) {
+    drawPlayer(player);
+  }
 
   // draw scores
-  const playerScore = playerId ? ubur.get_sphere_score(playerId) : 0;
+  const playerScore = player?.score ?? 0;
+  const playerRank = player?.rank ?? 0;
+
+  player?.free();
 
-  if (playerId !== undefined) {
+  if (player !== undefined) {
     drawScore(playerScore);
   }
 
+  const minimapPtr = ubur.get_minimap(MINIMAP_TOP_PLAYERS);
+  const minimap = new Float64Array(
+    memory.buffer,
+    minimapPtr,
+    ubur.get_minimap_len()
+  );
+
+  drawMinimap(minimap, playerId);
+
   if (!showHighscore) return;
 
   const hsidsPtr = ubur.get_top_5_player_ids();
   const hslen = new Uint32Array(memory.buffer, hsidsPtr, 1)[0];
-  const hsids = new Uint32Array(memory.buffer, hsidsPtr + 4, hslen);
+  const hsids = new Uint32Array(memory.buffer, hsidsPtr + 4, hslen).slice();
+
+  const hsViews = ubur.get_sphere_views(hsids);
 
-  for (let i = 0; i < hslen; i++) {
-    const id = hsids[i];
+  for (let i = 0; i < hsViews.length; i++) {
+    const view = hsViews[i];
 
-    hsScores[i] = ubur.get_sphere_score(id);
+    hsScores[i] = view.score;
 
-    if (id === playerId) {
+    if (view.id === playerId) {
       hsNames[i] = playerName;
     } else {
-      const nameIndex = ubur.get_sphere_name(id);
-
       hsNames[i] =
-        nameIndex === undefined ? emptyName : FAKE_PLAYER_NAMES[nameIndex];
+        view.name === undefined ? emptyName : FAKE_PLAYER_NAMES[view.name];
     }
   }
 
-  for (let i = hslen; i < 5; i++) {
+  for (let i = hsViews.length; i < 5; i++) {
     hsScores[i] = 0;
     hsNames[i] = emptyName;
   }
 
-  const playerRank = playerId ? ubur.get_sphere_rank(playerId) : 0;
+  freeViews(hsViews);
 
   drawHighscores(hsNames, hsScores, playerName, playerRank, playerScore);
 }
 
 function handleShoot(ev: MouseEvent) {
-  if (!playerId) return;
+  if (playerHandle === undefined || playerId === undefined) return;
 
-  const cx = window.innerWidth * 0.5;
-  const cy = window.innerHeight * 0.5;
+  const player = ubur.get_sphere_view(playerId);
 
-  const x = ev.clientX - cx;
-  const y = ev.clientY - cy;
+  if (player === undefined) return;
 
-  ubur.shoot(playerId, x, y);
+  const { x: px, y: py } = player;
+  player.free();
+
+  const x = ev.clientX - ubur.world_to_screen_x(px, py);
+  const y = ev.clientY - ubur.world_to_screen_y(px, py);
+
+  // shoot throws for a click right on the center, there is no direction
+  if (x === 0 && y === 0) return;
+
+  ubur.shoot(playerHandle, x, y);
+}
+
+function handleMove(ev: MouseEvent) {
+  if (playerHandle === undefined) return;
+  if (ubur.get_control_scheme() !== ControlScheme.MOVE) return;
+
+  const x = ubur.screen_to_world_x(ev.clientX, ev.clientY);
+  const y = ubur.screen_to_world_y(ev.clientX, ev.clientY);
+
+  ubur.move_to(playerHandle, x, y);
+}
+
+function handleSpectatorKey(ev: KeyboardEvent) {
+  if (playerHandle !== undefined || ev.target === nameInput) return;
+
+  switch (ev.key) {
+    case "ArrowRight":
+      ubur.spectate_next();
+      break;
+    case "ArrowLeft":
+      ubur.spectate_prev();
+      break;
+    case "l":
+      ubur.set_spectator_mode(SpectatorMode.LEADER);
+      break;
+    case "d":
+      ubur.set_spectator_mode(SpectatorMode.DIRECTOR);
+      break;
+    case "f":
+      ubur.set_spectator_mode(SpectatorMode.FREE);
+      break;
+  }
 }
 
 function resizeUI() {
@@ -306,6 +448,8 @@ async function main() {
   setViewPos(viewX, viewY);
 
   ubur = Ubur.new();
+  ubur.import_highscores(localStorage.getItem("highscores") || "");
+  ubur.import_achievements(localStorage.getItem("achievements") || "");
   ubur.init();
 
   nameInput.value = localStorage.getItem("player-name") || "";
@@ -315,17 +459,26 @@ async function main() {
     localStorage.setItem("player-name", nameInput.value);
 
     playerName = nameInput.value || "Anon";
-    const r = ubur.register_player(playerName);
+    playerHandle = ubur.register_player(playerName);
 
-    playerId = r[0];
-    playerUid = r[1];
+    if (playerHandle === undefined) {
+      // the world is full
+      titleUI.style.display = "flex";
+    } else {
+      playerId = ubur.get_sphere_id(playerHandle);
+
+      ubur.camera_follow(playerHandle);
+      ubur.camera_snap();
+    }
 
     e.stopPropagation();
     e.preventDefault();
     e.stopImmediatePropagation();
   });
 
+  window.addEventListener("keydown", handleSpectatorKey);
   window.addEventListener("click", handleShoot);
+  window.addEventListener("mousemove", handleMove);
 
   hsButton.addEventListener("click", e => {
     showHighscore = !showHighscore;
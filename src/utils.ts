@@ -11,6 +11,16 @@ export function setColorArr(out: Float32Array, color: number, opacity = 1) {
   }
 }
 
+// shortest signed distance from a to b on a loop of the given size
+export function wrapDelta(a: number, b: number, size: number) {
+  const d = b - a;
+
+  if (d > size * 0.5) return d - size;
+  if (d < -size * 0.5) return d + size;
+
+  return d;
+}
+
 export const FAKE_PLAYER_NAMES = [
   "Bambino",
   "Stud",